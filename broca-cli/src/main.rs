@@ -0,0 +1,563 @@
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use serde_json::json;
+use std::net::SocketAddr;
+
+use broca_core::anki::AnkiOptions;
+use broca_core::collection::Collection;
+use broca_core::conflict::Resolution;
+use broca_core::entry::{Entry, Lang};
+use broca_core::export::{ExportFormat, ExportRows};
+use broca_core::state::State;
+use broca_core::stream::SearchEvent;
+
+mod serve;
+
+// Works on the workspace of the GUI's config file, run the app once to set it up.
+// Diagnostics go to stderr, so with --json stdout is exactly one JSON document.
+#[derive(Parser)]
+#[command(name = "broca", about = "Look up words and manage the Broca vocabulary book")]
+struct Cli {
+    /// Print results as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum CollectionCommand {
+    /// Collections and how many of their words are in the vocabulary book
+    List,
+    /// Every word of a collection, in the order they were added
+    Show { name: String },
+    Create {
+        name: String,
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+    Delete { name: String },
+    /// Add words, creating the collection if needed
+    Add { name: String, words: Vec<String> },
+    Remove { name: String, words: Vec<String> },
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Look up a word, from the vocabulary book if it's already there
+    Search {
+        query: String,
+        /// Ask the model again and merge the answer into the saved entry
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Print a saved entry
+    Show { query: String },
+    /// List saved words, newest first
+    List {
+        /// Only words due for review
+        #[arg(long)]
+        due: bool,
+        /// Only words with this tag
+        #[arg(long, conflicts_with_all = ["due", "starred", "collection"])]
+        tag: Option<String>,
+        /// Only starred words
+        #[arg(long, conflicts_with_all = ["due", "collection"])]
+        starred: bool,
+        /// Only words in this collection, also with --due
+        #[arg(long)]
+        collection: Option<String>,
+        #[arg(long)]
+        limit: Option<i64>,
+    },
+    /// Full-text search over meanings and examples
+    Grep {
+        pattern: String,
+        #[arg(long)]
+        limit: Option<i64>,
+    },
+    /// Remove a word from the vocabulary book
+    Delete { query: String },
+    /// Generate more example sentences for one meaning of a saved word
+    Examples { query: String, meaning: String },
+    /// Synthesize speech, prints the path of the mp3
+    Say { text: String },
+    /// Export the vocabulary book, or the given words
+    Export {
+        path: String,
+        /// csv, tsv, markdown or anki, guessed from the extension by default
+        #[arg(long)]
+        format: Option<String>,
+        /// One row per meaning or per example
+        #[arg(long, default_value = "meaning")]
+        rows: String,
+        /// Deck name for Anki exports
+        #[arg(long)]
+        deck: Option<String>,
+        /// Export the words of this collection
+        #[arg(long, conflicts_with = "words")]
+        collection: Option<String>,
+        words: Vec<String>,
+    },
+    /// Tokens spent on lookups so far
+    Usage,
+    /// Re-index vocabulary/ and review/ into the cache
+    Rescan,
+    /// Words with more than one file in vocabulary/, e.g. sync conflicted copies
+    Conflicts,
+    /// Settle a conflict by keeping one file's version or merging all of them
+    Resolve {
+        query: String,
+        /// File whose version wins, as listed by `conflicts`
+        #[arg(long, conflicts_with = "merge", required_unless_present = "merge")]
+        keep: Option<String>,
+        /// Merge the meanings and examples of every version
+        #[arg(long)]
+        merge: bool,
+    },
+    /// Change a word's notes, tags or source, leaving out an option keeps what's there
+    Annotate {
+        query: String,
+        #[arg(long)]
+        notes: Option<String>,
+        /// Replaces the tags, repeat for several
+        #[arg(long = "tag")]
+        tags: Option<Vec<String>>,
+        /// The sentence the word was met in
+        #[arg(long)]
+        sentence: Option<String>,
+        /// The page the word was met on
+        #[arg(long)]
+        url: Option<String>,
+    },
+    Star { query: String },
+    Unstar { query: String },
+    /// Tags in use and how many words have each
+    Tags,
+    /// Named word lists
+    #[command(subcommand)]
+    Collection(CollectionCommand),
+    /// Commit local changes and merge in the git remote's
+    Pull,
+    /// Commit local changes and push them to the git remote
+    Push,
+    /// Commits that changed a word, newest first
+    History { query: String },
+    /// Bring a word back to how it was in a commit listed by `history`
+    Revert { query: String, commit: String },
+    /// Answer lookups over an HTTP/JSON API
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        addr: SocketAddr,
+        /// Clients send it as `Authorization: Bearer <token>`
+        #[arg(long, env = "BROCA_API_TOKEN", hide_env_values = true)]
+        token: String,
+    },
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn lang_text(lang: &Lang) -> &str {
+    match lang {
+        Lang::English(text)
+        | Lang::Chinese(text)
+        | Lang::Spanish(text)
+        | Lang::Japanese(text)
+        | Lang::Korean(text)
+        | Lang::German(text)
+        | Lang::French(text)
+        | Lang::Portuguese(text) => text,
+    }
+}
+
+fn print_entry(entry: &Entry) {
+    let annotations = &entry.annotations;
+
+    match annotations.starred {
+        true => println!("{} *", entry.query),
+        false => println!("{}", entry.query),
+    }
+    if !annotations.tags.is_empty() {
+        println!("  [{}]", annotations.tags.join(", "));
+    }
+
+    for meaning in &entry.meanings {
+        println!();
+        println!("  {}", meaning.pos);
+
+        for (i, item) in meaning.meanings.iter().enumerate() {
+            let mut texts = item.meaning.iter().map(lang_text);
+            println!("  {:>3}. {}", i + 1, texts.next().unwrap_or_default());
+            for text in texts {
+                println!("       {}", text);
+            }
+
+            for example in &item.examples {
+                let mut texts = example.iter().map(lang_text);
+                println!("       - {}", texts.next().unwrap_or_default());
+                for text in texts {
+                    println!("         {}", text);
+                }
+            }
+        }
+    }
+
+    if let Some(sentence) = &annotations.source_sentence {
+        println!();
+        println!("  \"{}\"", sentence);
+    }
+    if let Some(url) = &annotations.source_url {
+        println!("  {}", url);
+    }
+    if !annotations.notes.trim().is_empty() {
+        println!();
+        for line in annotations.notes.lines() {
+            println!("  {}", line);
+        }
+    }
+}
+
+// Entries are handed around as the JSON stored in vocabulary/.
+fn print_entry_str(content: &str, as_json: bool) -> Result<()> {
+    let entry: Entry = serde_json::from_str(content)?;
+
+    if as_json {
+        return print_json(&entry);
+    }
+
+    print_entry(&entry);
+    Ok(())
+}
+
+fn print_collection(collection: &Collection, as_json: bool) -> Result<()> {
+    if as_json {
+        return print_json(collection);
+    }
+
+    println!("{}", collection.name);
+    if !collection.description.is_empty() {
+        println!("  {}", collection.description);
+    }
+    for word in &collection.words {
+        println!("    {}", word);
+    }
+    Ok(())
+}
+
+fn run_collection(state: State, command: CollectionCommand, as_json: bool) -> Result<()> {
+    match command {
+        CollectionCommand::List => {
+            let collections = state.list_collections()?;
+            if as_json {
+                return print_json(&collections);
+            }
+            for collection in collections {
+                println!("{}\t{}", collection.name, collection.words);
+            }
+            Ok(())
+        }
+        CollectionCommand::Show { name } => print_collection(&state.load_collection(&name)?, as_json),
+        CollectionCommand::Create { name, description } => {
+            print_collection(&state.create_collection(&name, &description)?, as_json)
+        }
+        CollectionCommand::Delete { name } => {
+            state.delete_collection(&name)?;
+            if as_json {
+                return print_json(&json!({ "deleted": name }));
+            }
+            println!("Deleted {}", name);
+            Ok(())
+        }
+        CollectionCommand::Add { name, words } => {
+            print_collection(&state.add_to_collection(&name, &words)?, as_json)
+        }
+        CollectionCommand::Remove { name, words } => {
+            print_collection(&state.remove_from_collection(&name, &words)?, as_json)
+        }
+    }
+}
+
+fn print_words(words: &[String], as_json: bool) -> Result<()> {
+    if as_json {
+        return print_json(&words);
+    }
+
+    for word in words {
+        println!("{}", word);
+    }
+    Ok(())
+}
+
+fn export_format(format: Option<&str>, path: &str) -> Result<Option<ExportFormat>> {
+    let format = match format {
+        Some(format) => format.to_lowercase(),
+        None => path.rsplit('.').next().unwrap_or_default().to_lowercase(),
+    };
+
+    match format.as_str() {
+        "anki" | "apkg" => Ok(None),
+        "tsv" => Ok(Some(ExportFormat::Tsv)),
+        "markdown" | "md" => Ok(Some(ExportFormat::Markdown)),
+        "csv" => Ok(Some(ExportFormat::Csv)),
+        _ => Err(anyhow!(
+            "Unknown export format {}, expected csv, tsv, markdown or anki.",
+            format
+        )),
+    }
+}
+
+fn export_rows(rows: &str) -> Result<ExportRows> {
+    match rows.to_lowercase().as_str() {
+        "meaning" => Ok(ExportRows::Meaning),
+        "example" => Ok(ExportRows::Example),
+        _ => Err(anyhow!("Unknown row kind {}, expected meaning or example.", rows)),
+    }
+}
+
+async fn run(state: State, command: Command, as_json: bool) -> Result<()> {
+    match command {
+        Command::Search { query, refresh } => {
+            let content = state.search(&query, refresh, &|_: SearchEvent| {}).await?;
+            print_entry_str(&content, as_json)
+        }
+        Command::Show { query } => {
+            let content = state.load_word(&query.trim().to_lowercase())?;
+            print_entry_str(&content, as_json)
+        }
+        Command::List {
+            due,
+            tag,
+            starred,
+            collection,
+            limit,
+        } => {
+            let mut words = if due {
+                state.fetch_due_words(limit, collection.as_deref())?
+            } else if let Some(tag) = tag {
+                state.words_with_tag(&tag)?
+            } else if starred {
+                state.starred_words()?
+            } else {
+                state.fetch_all_words(collection.as_deref())?
+            };
+            if let (false, Some(limit)) = (due, limit) {
+                words.truncate(limit.max(0) as usize);
+            }
+            print_words(&words, as_json)
+        }
+        Command::Grep { pattern, limit } => {
+            let hits = state.full_text_search(&pattern, limit)?;
+            if as_json {
+                return print_json(&hits);
+            }
+            for hit in hits {
+                let snippet = hit.snippet.replace("<b>", "").replace("</b>", "");
+                println!("{}\t{}", hit.query, snippet);
+            }
+            Ok(())
+        }
+        Command::Delete { query } => {
+            let query = query.trim().to_lowercase();
+            let filename = state.delete_word(&query)?;
+            if as_json {
+                return print_json(&json!({ "query": query, "filename": filename }));
+            }
+            println!("Deleted {}", query);
+            Ok(())
+        }
+        Command::Examples { query, meaning } => {
+            let entry = state.load_word(&query.trim().to_lowercase())?;
+            let content = state.search_example_sentences(&entry, &meaning).await?;
+            print_entry_str(&content, as_json)
+        }
+        Command::Say { text } => {
+            let path = state.say(&text).await?;
+            if as_json {
+                return print_json(&json!({ "path": path }));
+            }
+            println!("{}", path);
+            Ok(())
+        }
+        Command::Export {
+            path,
+            format,
+            rows,
+            deck,
+            collection,
+            words,
+        } => {
+            let queries = if let Some(collection) = collection {
+                Some(state.fetch_all_words(Some(&collection))?)
+            } else if words.is_empty() {
+                None
+            } else {
+                Some(words.iter().map(|word| word.trim().to_lowercase()).collect())
+            };
+
+            match export_format(format.as_deref(), &path)? {
+                Some(format) => {
+                    let rows_written =
+                        state.export_entries(&path, queries, &format, &export_rows(&rows)?)?;
+                    if as_json {
+                        return print_json(&json!({ "path": path, "rows": rows_written }));
+                    }
+                    println!("Wrote {} rows to {}", rows_written, path);
+                }
+                None => {
+                    let mut options = AnkiOptions::default();
+                    if let Some(deck) = deck {
+                        options.deck_name = deck;
+                    }
+                    let report = state.export_anki(&path, queries, &options)?;
+                    if as_json {
+                        return print_json(&report);
+                    }
+                    println!(
+                        "Wrote {} notes and {} audio files to {}",
+                        report.notes, report.media, path
+                    );
+                }
+            }
+            Ok(())
+        }
+        Command::Usage => {
+            let [prompt_tokens, completion_tokens] = state.load_usage()?;
+            if as_json {
+                return print_json(&json!({
+                    "prompt_tokens": prompt_tokens,
+                    "completion_tokens": completion_tokens,
+                }));
+            }
+            println!("prompt tokens:     {}", prompt_tokens);
+            println!("completion tokens: {}", completion_tokens);
+            Ok(())
+        }
+        Command::Rescan => {
+            let words = state.scan_vocabulary()?;
+            if as_json {
+                return print_words(&words, true);
+            }
+            println!("{} words in the vocabulary book", words.len());
+            Ok(())
+        }
+        Command::Conflicts => {
+            let conflicts = state.list_conflicts()?;
+            if as_json {
+                return print_json(&conflicts);
+            }
+            for conflict in conflicts {
+                println!("{}", conflict.query);
+                for version in conflict.versions {
+                    println!("    {} ({})", version.filename, version.modified);
+                }
+            }
+            Ok(())
+        }
+        Command::Resolve { query, keep, merge } => {
+            let resolution = match (keep, merge) {
+                (Some(filename), false) => Resolution::Keep { filename },
+                _ => Resolution::Merge,
+            };
+            let content = state.resolve_conflict(&query.trim().to_lowercase(), &resolution)?;
+            print_entry_str(&content, as_json)
+        }
+        Command::Annotate {
+            query,
+            notes,
+            tags,
+            sentence,
+            url,
+        } => {
+            let query = query.trim().to_lowercase();
+            let entry: Entry = serde_json::from_str(&state.load_word(&query)?)?;
+            let mut annotations = entry.annotations;
+            if let Some(notes) = notes {
+                annotations.notes = notes;
+            }
+            if let Some(tags) = tags {
+                annotations.tags = tags;
+            }
+            if let Some(sentence) = sentence {
+                annotations.source_sentence = Some(sentence);
+            }
+            if let Some(url) = url {
+                annotations.source_url = Some(url);
+            }
+            let content = state.update_annotations(&query, &annotations)?;
+            print_entry_str(&content, as_json)
+        }
+        Command::Star { query } => {
+            let content = state.set_starred(&query.trim().to_lowercase(), true)?;
+            print_entry_str(&content, as_json)
+        }
+        Command::Unstar { query } => {
+            let content = state.set_starred(&query.trim().to_lowercase(), false)?;
+            print_entry_str(&content, as_json)
+        }
+        Command::Tags => {
+            let tags = state.list_tags()?;
+            if as_json {
+                return print_json(&tags);
+            }
+            for tag in tags {
+                println!("{}\t{}", tag.tag, tag.words);
+            }
+            Ok(())
+        }
+        Command::Collection(command) => run_collection(state, command, as_json),
+        Command::Pull => {
+            let report = state.pull_workspace()?;
+            if as_json {
+                return print_json(&report);
+            }
+            println!("{} commits received", report.received);
+            for copy in report.conflicted_copies {
+                println!("conflict: {}", copy);
+            }
+            Ok(())
+        }
+        Command::Push => {
+            state.push_workspace()?;
+            if as_json {
+                return print_json(&json!({ "pushed": true }));
+            }
+            println!("Pushed");
+            Ok(())
+        }
+        Command::History { query } => {
+            let history = state.word_history(&query.trim().to_lowercase())?;
+            if as_json {
+                return print_json(&history);
+            }
+            for revision in history {
+                println!("{} {} {}", &revision.commit[..7], revision.time, revision.summary);
+            }
+            Ok(())
+        }
+        Command::Revert { query, commit } => {
+            let content = state.revert_word(&query.trim().to_lowercase(), &commit)?;
+            print_entry_str(&content, as_json)
+        }
+        Command::Serve { addr, token } => {
+            if token.is_empty() {
+                return Err(anyhow!("The API token can't be empty."));
+            }
+            serve::serve(state, addr, token).await
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let mut state = State::new();
+    state.load_config()?;
+
+    run(state, cli.command, cli.json).await
+}
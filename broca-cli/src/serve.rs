@@ -0,0 +1,180 @@
+use anyhow::Result;
+use axum::extract::{Path, Query, State as AxumState};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+
+use broca_core::state::State;
+
+// The same operations as the app's Tauri commands, for clients on other machines.
+// Every request needs `Authorization: Bearer <token>`.
+#[derive(Clone)]
+struct Server {
+    state: State,
+    token: String,
+}
+
+struct ApiError(StatusCode, String);
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+// Entries are already serialized JSON, pass them through as is.
+fn json_body(content: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], content).into_response()
+}
+
+// Compares every byte so the time taken doesn't tell how much of a guess was right.
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn authorize<B>(
+    AxumState(server): AxumState<Server>,
+    headers: HeaderMap,
+    request: axum::http::Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match given {
+        Some(given) if token_matches(&server.token, given) => Ok(next.run(request).await),
+        _ => Err(ApiError(
+            StatusCode::UNAUTHORIZED,
+            String::from("Missing or wrong API token."),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    query: String,
+    #[serde(default)]
+    refresh: bool,
+}
+
+async fn search(
+    AxumState(server): AxumState<Server>,
+    Query(params): Query<SearchParams>,
+) -> Result<Response, ApiError> {
+    let content = server
+        .state
+        .search(&params.query, params.refresh, &|_| {})
+        .await?;
+    Ok(json_body(content))
+}
+
+async fn load_word(
+    AxumState(server): AxumState<Server>,
+    Path(query): Path<String>,
+) -> Result<Response, ApiError> {
+    match server.state.load_word(&query.to_lowercase()) {
+        Ok(content) => Ok(json_body(content)),
+        Err(message) => Err(ApiError(StatusCode::NOT_FOUND, message.to_string())),
+    }
+}
+
+#[derive(Deserialize)]
+struct WordsParams {
+    #[serde(default)]
+    query: String,
+    collection: Option<String>,
+}
+
+async fn query_words(
+    AxumState(server): AxumState<Server>,
+    Query(params): Query<WordsParams>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    Ok(Json(server.state.query_words(&params.query, params.collection.as_deref())?))
+}
+
+#[derive(Deserialize)]
+struct ExamplesRequest {
+    // the entry as returned by search or load_word
+    entry: serde_json::Value,
+    meaning: String,
+}
+
+async fn generate_more_examples(
+    AxumState(server): AxumState<Server>,
+    Json(request): Json<ExamplesRequest>,
+) -> Result<Response, ApiError> {
+    let entry = serde_json::to_string(&request.entry).map_err(anyhow::Error::from)?;
+    let content = server
+        .state
+        .search_example_sentences(&entry, &request.meaning)
+        .await?;
+    Ok(json_body(content))
+}
+
+#[derive(Deserialize)]
+struct SayParams {
+    text: String,
+}
+
+// Answers with the mp3 itself, the path only means something on this machine.
+async fn say(
+    AxumState(server): AxumState<Server>,
+    Query(params): Query<SayParams>,
+) -> Result<Response, ApiError> {
+    let path = server.state.say(&params.text).await?;
+    let audio = std::fs::read(path).map_err(anyhow::Error::from)?;
+    Ok(([(header::CONTENT_TYPE, "audio/mpeg")], audio).into_response())
+}
+
+async fn usage(AxumState(server): AxumState<Server>) -> Result<Response, ApiError> {
+    let [prompt_tokens, completion_tokens] = server.state.load_usage()?;
+    Ok(Json(json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+    }))
+    .into_response())
+}
+
+pub async fn serve(state: State, addr: SocketAddr, token: String) -> Result<()> {
+    // pick up words synced in from other devices, now and while serving
+    state.scan_vocabulary()?;
+    let _watcher = state.watch_vocabulary(|event| eprintln!("{:?}", event))?;
+
+    let server = Server { state, token };
+
+    let app = Router::new()
+        .route("/api/search", get(search))
+        .route("/api/words", get(query_words))
+        .route("/api/words/:query", get(load_word))
+        .route("/api/examples", post(generate_more_examples))
+        .route("/api/say", get(say))
+        .route("/api/usage", get(usage))
+        .route_layer(middleware::from_fn_with_state(server.clone(), authorize))
+        .with_state(server);
+
+    eprintln!("Listening on http://{}", addr);
+
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,342 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::entry::{Entry, Lang};
+use crate::state::audio_filename;
+
+// Anki's field separator inside notes.flds
+const FIELD_SEPARATOR: &str = "\x1f";
+const FIELDS: [&str; 4] = ["Word", "Meanings", "Examples", "Audio"];
+
+// Legacy collection schema (version 11), which every Anki release still imports.
+const SCHEMA: &str = "
+CREATE TABLE col (id integer primary key, crt integer not null, mod integer not null, scm integer not null, ver integer not null, dty integer not null, usn integer not null, ls integer not null, conf text not null, models text not null, decks text not null, dconf text not null, tags text not null);
+CREATE TABLE notes (id integer primary key, guid text not null, mid integer not null, mod integer not null, usn integer not null, tags text not null, flds text not null, sfld integer not null, csum integer not null, flags integer not null, data text not null);
+CREATE TABLE cards (id integer primary key, nid integer not null, did integer not null, ord integer not null, mod integer not null, usn integer not null, type integer not null, queue integer not null, due integer not null, ivl integer not null, factor integer not null, reps integer not null, lapses integer not null, left integer not null, odue integer not null, odid integer not null, flags integer not null, data text not null);
+CREATE TABLE revlog (id integer primary key, cid integer not null, usn integer not null, ease integer not null, ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null, type integer not null);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+// Templates use Anki's own syntax, fields are {{Word}}, {{Meanings}}, {{Examples}} and {{Audio}}.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AnkiOptions {
+    pub deck_name: String,
+    pub front_template: String,
+    pub back_template: String,
+    pub css: String,
+    pub include_audio: bool,
+}
+
+impl Default for AnkiOptions {
+    fn default() -> Self {
+        AnkiOptions {
+            deck_name: String::from("Broca"),
+            front_template: String::from("<div class=\"word\">{{Word}}</div>\n{{Audio}}"),
+            back_template: String::from(
+                "{{FrontSide}}\n<hr id=\"answer\">\n<div class=\"meanings\">{{Meanings}}</div>\n<div class=\"examples\">{{Examples}}</div>",
+            ),
+            css: String::from(
+                ".card { font-family: arial; font-size: 18px; text-align: left; color: black; background-color: white; }\n.word { font-size: 32px; text-align: center; }\n.pos { color: gray; font-style: italic; }\n.examples { color: #444; }",
+            ),
+            include_audio: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct AnkiExportReport {
+    pub notes: usize,
+    pub media: usize,
+}
+
+fn sha1_hex(text: &str) -> String {
+    sha1_smol::Sha1::from(text).digest().to_string()
+}
+
+// Ids derived from names stay the same between exports, so importing again
+// updates the existing note type and deck instead of creating copies.
+fn stable_id(text: &str) -> i64 {
+    i64::from_str_radix(&sha1_hex(text)[..12], 16).unwrap_or(1)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn lang_text(lang: &Lang) -> &str {
+    match lang {
+        Lang::English(s)
+        | Lang::Chinese(s)
+        | Lang::Spanish(s)
+        | Lang::Japanese(s)
+        | Lang::Korean(s)
+        | Lang::German(s)
+        | Lang::French(s)
+        | Lang::Portuguese(s) => s,
+    }
+}
+
+fn langs_html(langs: &[Lang]) -> String {
+    langs
+        .iter()
+        .map(|l| escape_html(lang_text(l)))
+        .collect::<Vec<String>>()
+        .join("<br>")
+}
+
+fn meanings_html(entry: &Entry) -> String {
+    let mut html = String::new();
+    for meaning in &entry.meanings {
+        html.push_str(&format!("<div class=\"pos\">{}</div><ol>", escape_html(&meaning.pos)));
+        for item in &meaning.meanings {
+            html.push_str(&format!("<li>{}</li>", langs_html(&item.meaning)));
+        }
+        html.push_str("</ol>");
+    }
+    html
+}
+
+fn examples_html(entry: &Entry) -> String {
+    let mut html = String::from("<ul>");
+    for meaning in &entry.meanings {
+        for item in &meaning.meanings {
+            for example in &item.examples {
+                html.push_str(&format!("<li>{}</li>", langs_html(example)));
+            }
+        }
+    }
+    html.push_str("</ul>");
+    html
+}
+
+fn model(options: &AnkiOptions, model_id: i64, deck_id: i64, now: i64) -> serde_json::Value {
+    let fields: Vec<serde_json::Value> = FIELDS
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            json!({"name": name, "ord": ord, "sticky": false, "rtl": false, "font": "Arial", "size": 20, "media": []})
+        })
+        .collect();
+
+    json!({
+        "id": model_id,
+        "name": format!("{} (Broca)", options.deck_name),
+        "type": 0,
+        "mod": now,
+        "usn": -1,
+        "sortf": 0,
+        "did": deck_id,
+        "tmpls": [{
+            "name": "Card 1",
+            "ord": 0,
+            "qfmt": options.front_template,
+            "afmt": options.back_template,
+            "did": null,
+            "bqfmt": "",
+            "bafmt": ""
+        }],
+        "flds": fields,
+        "css": options.css,
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "latexsvg": false,
+        "req": [[0, "any", [0]]],
+        "tags": [],
+        "vers": []
+    })
+}
+
+fn deck(id: i64, name: &str, now: i64) -> serde_json::Value {
+    json!({
+        "id": id,
+        "name": name,
+        "desc": "",
+        "mod": now,
+        "usn": -1,
+        "collapsed": false,
+        "browserCollapsed": false,
+        "newToday": [0, 0],
+        "revToday": [0, 0],
+        "lrnToday": [0, 0],
+        "timeToday": [0, 0],
+        "dyn": 0,
+        "conf": 1,
+        "extendNew": 10,
+        "extendRev": 50
+    })
+}
+
+fn deck_config(now: i64) -> serde_json::Value {
+    json!({
+        "id": 1,
+        "name": "Default",
+        "mod": now,
+        "usn": 0,
+        "maxTaken": 60,
+        "autoplay": true,
+        "timer": 0,
+        "replayq": true,
+        "dyn": false,
+        "new": {"delays": [1, 10], "ints": [1, 4, 7], "initialFactor": 2500, "order": 1, "perDay": 20, "bury": true, "separate": true},
+        "rev": {"perDay": 100, "ease4": 1.3, "fuzz": 0.05, "ivlFct": 1, "maxIvl": 36500, "bury": true, "minSpace": 1},
+        "lapse": {"delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 0}
+    })
+}
+
+// Writes the collection database for `entries` into `path`. `media` holds the
+// filenames referenced by the Audio field, in the order they'll be packed.
+fn write_collection(
+    path: &Path,
+    entries: &[Entry],
+    media: &[Option<String>],
+    options: &AnkiOptions,
+) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let now_secs = now.as_secs() as i64;
+    let now_millis = now.as_millis() as i64;
+
+    let model_id = stable_id(&format!("model:{}", options.deck_name));
+    let deck_id = stable_id(&format!("deck:{}", options.deck_name));
+
+    let conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+
+    let conf = json!({
+        "activeDecks": [1],
+        "curDeck": 1,
+        "newSpread": 0,
+        "collapseTime": 1200,
+        "timeLim": 0,
+        "estTimes": true,
+        "dueCounts": true,
+        "curModel": model_id.to_string(),
+        "nextPos": entries.len() + 1,
+        "sortType": "noteFld",
+        "sortBackwards": false,
+        "addToCur": true
+    });
+    let models = json!({ model_id.to_string(): model(options, model_id, deck_id, now_secs) });
+    let decks = json!({
+        "1": deck(1, "Default", now_secs),
+        deck_id.to_string(): deck(deck_id, &options.deck_name, now_secs)
+    });
+    let dconf = json!({ "1": deck_config(now_secs) });
+
+    conn.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}');",
+        (
+            now_secs,
+            now_millis,
+            conf.to_string(),
+            models.to_string(),
+            decks.to_string(),
+            dconf.to_string(),
+        ),
+    )?;
+
+    for (index, (entry, audio)) in entries.iter().zip(media).enumerate() {
+        let id = now_millis + index as i64;
+        let sound = match audio {
+            Some(filename) => format!("[sound:{}]", filename),
+            None => String::new(),
+        };
+        let fields = [
+            escape_html(&entry.query),
+            meanings_html(entry),
+            examples_html(entry),
+            sound,
+        ];
+        // checksum of the sort field, the first 8 hex digits of its sha1
+        let csum = i64::from_str_radix(&sha1_hex(&entry.query)[..8], 16)?;
+
+        conn.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, '', ?5, ?6, ?7, 0, '');",
+            (
+                id,
+                sha1_hex(&format!("broca:{}", entry.query)),
+                model_id,
+                now_secs,
+                fields.join(FIELD_SEPARATOR),
+                &entry.query,
+                csum,
+            ),
+        )?;
+        conn.execute(
+            "INSERT INTO cards VALUES (?1, ?1, ?2, 0, ?3, -1, 0, 0, ?4, 0, 0, 0, 0, 0, 0, 0, 0, '');",
+            (id, deck_id, now_secs, index as i64 + 1),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Packs entries into an .apkg at `output`. A word's audio is attached when `say` has
+// already cached it in `audio_dir`, nothing is synthesized here.
+pub fn export_apkg(
+    entries: &[Entry],
+    audio_dir: &Path,
+    output: &Path,
+    options: &AnkiOptions,
+) -> Result<AnkiExportReport> {
+    let media: Vec<Option<String>> = entries
+        .iter()
+        .map(|entry| {
+            let filename = audio_filename(&entry.query);
+            if options.include_audio && audio_dir.join(&filename).exists() {
+                Some(filename)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let temp_path = output.with_extension("anki2.tmp");
+    if temp_path.exists() {
+        std::fs::remove_file(&temp_path)?;
+    }
+    write_collection(&temp_path, entries, &media, options)?;
+    let collection = std::fs::read(&temp_path)?;
+    std::fs::remove_file(&temp_path)?;
+
+    let mut zip = zip::ZipWriter::new(File::create(output)?);
+    let zip_options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file("collection.anki2", zip_options)?;
+    zip.write_all(&collection)?;
+
+    // media files are stored as "0", "1", ... and the `media` map gives their real names
+    let mut media_map = serde_json::Map::new();
+    for filename in media.iter().flatten() {
+        let index = media_map.len().to_string();
+        zip.start_file(index.as_str(), zip_options)?;
+        zip.write_all(&std::fs::read(audio_dir.join(filename))?)?;
+        media_map.insert(index, serde_json::Value::String(filename.clone()));
+    }
+
+    zip.start_file("media", zip_options)?;
+    zip.write_all(serde_json::Value::Object(media_map.clone()).to_string().as_bytes())?;
+    zip.finish()?;
+
+    Ok(AnkiExportReport {
+        notes: entries.len(),
+        media: media_map.len(),
+    })
+}
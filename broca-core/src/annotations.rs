@@ -0,0 +1,120 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+// What the user wrote down about a word, as opposed to what the model returned.
+// Lookups and refreshes never change these. Every field has a default, so files from
+// before annotations existed, and entries that have none, read the same way.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Annotations {
+    pub notes: String,
+    pub tags: Vec<String>,
+    // where the word was met: the sentence it was in and the page it was on
+    pub source_sentence: Option<String>,
+    pub source_url: Option<String>,
+    pub starred: bool,
+    // seconds since the epoch, None for words saved before this was recorded
+    pub added: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TagCount {
+    pub tag: String,
+    pub words: i64,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self == &Annotations::default()
+    }
+
+    // Trims tags and drops empty and repeated ones, comparing case insensitively.
+    pub fn normalize(&mut self) {
+        let mut tags = Vec::<String>::new();
+        for tag in &self.tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_string());
+            }
+        }
+        self.tags = tags;
+
+        for field in [&mut self.source_sentence, &mut self.source_url] {
+            if field.as_deref().map(str::trim) == Some("") {
+                *field = None;
+            }
+        }
+    }
+}
+
+// Combines the annotations of two versions of a word, `ours` winning where they
+// can't both be kept: tags are united, differing notes are both kept, a star on
+// either side stays and the word was added when the earlier version says so.
+pub fn merge_annotations(ours: Annotations, theirs: Annotations) -> Annotations {
+    let mut merged = ours;
+
+    merged.tags.extend(theirs.tags);
+
+    let notes = theirs.notes.trim();
+    if !notes.is_empty() && !merged.notes.contains(notes) {
+        if !merged.notes.trim().is_empty() {
+            merged.notes.push_str("\n\n");
+        }
+        merged.notes.push_str(notes);
+    }
+
+    merged.source_sentence = merged.source_sentence.or(theirs.source_sentence);
+    merged.source_url = merged.source_url.or(theirs.source_url);
+    merged.starred |= theirs.starred;
+    merged.added = match (merged.added, theirs.added) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    merged.normalize();
+    merged
+}
+
+// Copies the fields that are queried on into the word's vocabulary row and the tags
+// table. The row has to exist already.
+pub fn index_annotations(conn: &Connection, query: &str, annotations: &Annotations) -> Result<()> {
+    let query = query.to_lowercase();
+
+    conn.prepare_cached("UPDATE vocabulary SET starred = ?1, added = ?2 WHERE query = ?3;")?
+        .execute((annotations.starred, annotations.added, &query))?;
+
+    remove_annotations(conn, &query)?;
+    let mut stmt = conn.prepare_cached("INSERT OR IGNORE INTO tags(query, tag) VALUES (?1, ?2);")?;
+    for tag in &annotations.tags {
+        stmt.execute((&query, tag))?;
+    }
+
+    Ok(())
+}
+
+pub fn remove_annotations(conn: &Connection, query: &str) -> Result<()> {
+    conn.prepare_cached("DELETE FROM tags WHERE query = ?1;")?
+        .execute([query])?;
+    Ok(())
+}
+
+// Every tag in use, most used first.
+pub fn list_tags(conn: &Connection) -> Result<Vec<TagCount>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT tag, COUNT(*) AS words FROM tags GROUP BY tag COLLATE NOCASE ORDER BY words DESC, tag COLLATE NOCASE;",
+    )?;
+    let tag_iter = stmt.query_map((), |row| {
+        rusqlite::Result::Ok(TagCount {
+            tag: row.get(0)?,
+            words: row.get(1)?,
+        })
+    })?;
+
+    let mut result = Vec::<TagCount>::new();
+    for tag in tag_iter {
+        result.push(tag?);
+    }
+
+    Ok(result)
+}
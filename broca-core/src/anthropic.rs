@@ -0,0 +1,163 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use std::result::Result::Ok;
+
+use crate::openai::Message;
+use crate::provider::{Completion, DictionaryProvider, HttpStatusError};
+use crate::schema::ResponseSchema;
+
+pub const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const MAX_TOKENS: i64 = 4096;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessagesQuery {
+    model: String,
+    max_tokens: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<Message>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Root {
+    pub id: String,
+    pub model: String,
+    pub content: Vec<ContentBlock>,
+    pub usage: Usage,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContentBlock {
+    #[serde(rename = "type")]
+    pub block_type: String,
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+}
+
+pub struct AnthropicProvider {
+    base_url: String,
+    api_key: String,
+    model: String,
+    parse_attempts: usize,
+}
+
+impl AnthropicProvider {
+    pub fn new(base_url: &str, api_key: &str, model: &str, parse_attempts: usize) -> Self {
+        AnthropicProvider {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            parse_attempts,
+        }
+    }
+}
+
+#[async_trait]
+impl DictionaryProvider for AnthropicProvider {
+    fn parse_attempts(&self) -> usize {
+        self.parse_attempts
+    }
+
+    // No JSON schema support here, replies are shaped by the few-shot prompt alone.
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        _schema: Option<&ResponseSchema>,
+    ) -> Result<Completion> {
+        // the messages API takes the system prompt separately from the conversation
+        let mut system: Option<String> = None;
+        let mut conversation = Vec::<Message>::new();
+        for m in messages {
+            if m.role == "system" {
+                system = Some(m.content);
+            } else {
+                conversation.push(m);
+            }
+        }
+
+        let data = serde_json::to_string(&MessagesQuery {
+            model: self.model.clone(),
+            max_tokens: MAX_TOKENS,
+            system,
+            messages: conversation,
+        })?;
+
+        eprintln!("{}", data);
+
+        let url = format!("{}/messages", self.base_url);
+        let client = reqwest::Client::new();
+        let response = client
+            .post(url)
+            .header(ACCEPT, "*/*")
+            .header(CONTENT_TYPE, "application/json")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .body(data)
+            .send()
+            .await?;
+        match response.status() {
+            reqwest::StatusCode::OK => {
+                match response.json::<Root>().await {
+                    Ok(parsed) => {
+                        eprintln!("🔥 Success!");
+                        let content: String = parsed
+                            .content
+                            .iter()
+                            .filter(|block| block.block_type == "text")
+                            .map(|block| block.text.as_str())
+                            .collect();
+                        eprintln!("💬 Response: {}", content);
+                        return Ok(Completion {
+                            content,
+                            prompt_tokens: parsed.usage.input_tokens,
+                            completion_tokens: parsed.usage.output_tokens,
+                        });
+                    }
+                    Err(_) => {
+                        eprintln!("🛑 Hm, the response didn't match the shape we expected.");
+                        return Err(anyhow!(
+                            "🛑 Hm, the response didn't match the shape we expected."
+                        ));
+                    }
+                };
+            }
+            reqwest::StatusCode::UNAUTHORIZED => {
+                eprintln!("🛑 Status: UNAUTHORIZED - Check your Anthropic API key");
+                return Err(HttpStatusError {
+                    status: 401,
+                    message: String::from("Status: UNAUTHORIZED - Check your Anthropic API key"),
+                }
+                .into());
+            }
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                eprintln!("🛑 Status: 429 - Too many requests");
+                return Err(HttpStatusError {
+                    status: 429,
+                    message: String::from("Status: 429 - Too many requests. Please try again later."),
+                }
+                .into());
+            }
+            other => {
+                return Err(HttpStatusError {
+                    status: other.as_u16(),
+                    message: format!(
+                        "🛑 Uh oh! Something unexpected happened: [{:#?} {:?}]",
+                        other,
+                        response.text().await
+                    ),
+                }
+                .into());
+            }
+        };
+    }
+}
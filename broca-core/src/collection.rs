@@ -0,0 +1,125 @@
+use anyhow::{anyhow, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+// A named word list ("GRE list", "Book: Dune"), saved as collections/<name>.json so it
+// syncs with the rest of the workspace. Words are queries as in the vocabulary book;
+// ones that aren't in it (deleted, or not looked up yet) stay listed but are left out
+// of filtered word lists.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Collection {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    // in the order they were added
+    #[serde(default)]
+    pub words: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CollectionSummary {
+    pub name: String,
+    // members that are in the vocabulary book
+    pub words: i64,
+}
+
+impl Collection {
+    pub fn new(name: &str) -> Result<Collection> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("A collection needs a name."));
+        }
+
+        Ok(Collection {
+            name: name.to_string(),
+            description: String::new(),
+            words: Vec::new(),
+        })
+    }
+
+    // Returns how many of `queries` weren't members yet.
+    pub fn add(&mut self, queries: &[String]) -> usize {
+        let mut added = 0;
+        for query in queries {
+            let query = query.trim().to_lowercase();
+            if !query.is_empty() && !self.words.contains(&query) {
+                self.words.push(query);
+                added += 1;
+            }
+        }
+        added
+    }
+
+    // Returns how many of `queries` were members.
+    pub fn remove(&mut self, queries: &[String]) -> usize {
+        let queries: Vec<String> = queries.iter().map(|q| q.trim().to_lowercase()).collect();
+        let before = self.words.len();
+        self.words.retain(|word| !queries.contains(word));
+        before - self.words.len()
+    }
+}
+
+// Records one file of collections/ and its members, replacing what the cache had
+// for that file.
+pub fn index_collection(
+    conn: &Connection,
+    filename: &str,
+    collection: &Collection,
+    seconds: u64,
+) -> Result<()> {
+    remove_collection_file(conn, filename)?;
+
+    conn.prepare_cached("INSERT INTO collections(filename, name, timestamp) VALUES (?1, ?2, ?3);")?
+        .execute((filename, &collection.name, seconds))?;
+
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR IGNORE INTO collection_words(filename, query, position) VALUES (?1, ?2, ?3);",
+    )?;
+    for (position, query) in collection.words.iter().enumerate() {
+        stmt.execute((filename, query.to_lowercase(), position as i64))?;
+    }
+
+    Ok(())
+}
+
+pub fn remove_collection_file(conn: &Connection, filename: &str) -> Result<()> {
+    conn.prepare_cached("DELETE FROM collection_words WHERE filename = ?1;")?
+        .execute([filename])?;
+    conn.prepare_cached("DELETE FROM collections WHERE filename = ?1;")?
+        .execute([filename])?;
+    Ok(())
+}
+
+// The files holding the collection: one normally, more after a sync conflict, in
+// which case its members are the union of theirs.
+pub fn collection_files(conn: &Connection, name: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT filename FROM collections WHERE name = ?1 COLLATE NOCASE ORDER BY timestamp DESC, filename;",
+    )?;
+    let filenames = stmt
+        .query_map([name.trim()], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(filenames)
+}
+
+pub fn list_collections(conn: &Connection) -> Result<Vec<CollectionSummary>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT collections.name, COUNT(DISTINCT vocabulary.query) FROM collections
+        LEFT JOIN collection_words ON collection_words.filename = collections.filename
+        LEFT JOIN vocabulary ON vocabulary.query = collection_words.query
+        GROUP BY collections.name COLLATE NOCASE ORDER BY collections.name COLLATE NOCASE;",
+    )?;
+    let collection_iter = stmt.query_map((), |row| {
+        rusqlite::Result::Ok(CollectionSummary {
+            name: row.get(0)?,
+            words: row.get(1)?,
+        })
+    })?;
+
+    let mut result = Vec::<CollectionSummary>::new();
+    for collection in collection_iter {
+        result.push(collection?);
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use crate::entry::Entry;
+use crate::merge::merge_entries;
+
+// One file of vocabulary/ holding a version of a word that other files hold too, e.g.
+// `serendipity (conflicted copy).json` from Dropbox or a second slug for the same query.
+#[derive(Debug, Serialize, Clone)]
+pub struct ConflictVersion {
+    pub filename: String,
+    // modification time, seconds since the epoch
+    pub modified: u64,
+    pub entry: Entry,
+}
+
+// Versions are newest first.
+#[derive(Debug, Serialize, Clone)]
+pub struct Conflict {
+    pub query: String,
+    pub versions: Vec<ConflictVersion>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Resolution {
+    // the version in this file wins, the others are dropped
+    Keep { filename: String },
+    // everything from every version survives, see merge_versions
+    Merge,
+}
+
+// Folds the older versions into the newest one, the same way a refreshed lookup is
+// merged into a saved entry: matching senses get the union of their examples and
+// translations, senses only one version has are kept.
+pub fn merge_versions(versions: &[ConflictVersion]) -> Option<Entry> {
+    let (newest, older) = versions.split_first()?;
+
+    let mut merged = newest.entry.clone();
+    for version in older {
+        let (entry, report) = merge_entries(merged, version.entry.clone());
+        eprintln!("merged {} {:?}", version.filename, report);
+        merged = entry;
+    }
+
+    Some(merged)
+}
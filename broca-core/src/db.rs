@@ -0,0 +1,91 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Connections stay open for the life of the workspace instead of being reopened per
+// call, so SQLite's page cache and each connection's prepared statement cache survive.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+// how long a writer waits for another one before SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+// idle connections kept around, more are opened under load and closed when returned
+const MAX_IDLE: usize = 4;
+
+// A small pool of connections to cache.db. Clones share the pool.
+// With WAL readers don't block the writer or each other, so a long import doesn't
+// stall listing words.
+#[derive(Clone)]
+pub struct Database {
+    path: PathBuf,
+    idle: Arc<Mutex<Vec<Connection>>>,
+}
+
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    idle: Arc<Mutex<Vec<Connection>>>,
+}
+
+impl Database {
+    // Nothing is opened until the first get, the workspace may not exist yet.
+    pub fn new(path: &Path) -> Self {
+        Database {
+            path: path.to_path_buf(),
+            idle: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn open(&self) -> Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        // journal_mode answers with the resulting mode, so it has to be queried
+        conn.query_row("PRAGMA journal_mode = WAL;", (), |row| row.get::<_, String>(0))?;
+        conn.execute("PRAGMA synchronous = NORMAL;", ())?;
+        Ok(conn)
+    }
+
+    // Closes the idle connections, e.g. before the file is deleted. Connections
+    // checked out at the time aren't affected.
+    pub fn close_idle(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self) -> Result<PooledConnection> {
+        let idle = self.idle.lock().unwrap().pop();
+
+        let conn = match idle {
+            Some(conn) => conn,
+            None => self.open()?,
+        };
+
+        Ok(PooledConnection {
+            conn: Some(conn),
+            idle: self.idle.clone(),
+        })
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            let mut idle = self.idle.lock().unwrap();
+            if idle.len() < MAX_IDLE {
+                idle.push(conn);
+            }
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::Annotations;
+use crate::state::TargetLang;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Lang {
+    English(String),
+    Chinese(String),
+    Spanish(String),
+    Japanese(String),
+    Korean(String),
+    German(String),
+    French(String),
+    Portuguese(String)
+}
+
+impl Lang {
+    pub fn target_lang(&self) -> Option<TargetLang> {
+        match self {
+            Lang::English(_) => None,
+            Lang::Chinese(_) => Some(TargetLang::Chinese),
+            Lang::Spanish(_) => Some(TargetLang::Spanish),
+            Lang::Japanese(_) => Some(TargetLang::Japanese),
+            Lang::Korean(_) => Some(TargetLang::Korean),
+            Lang::German(_) => Some(TargetLang::German),
+            Lang::French(_) => Some(TargetLang::French),
+            Lang::Portuguese(_) => Some(TargetLang::Portuguese),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Item {
+    pub meaning: Vec<Lang>,
+    pub examples: Vec<Vec<Lang>>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Meaning {
+    pub pos: String,
+    pub meanings: Vec<Item>
+}
+
+pub type Meanings = Vec<Meaning>;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub query: String,
+    pub meanings: Meanings,
+    // set for reverse lookups, where the query is in a target language and the
+    // meanings are English candidates. None means an English query.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_lang: Option<TargetLang>,
+    // left out of files and prompts while empty
+    #[serde(default, skip_serializing_if = "Annotations::is_empty")]
+    pub annotations: Annotations,
+}
@@ -0,0 +1,358 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::entry::{Entry, Lang};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ExportFormat {
+    Csv,
+    Tsv,
+    Markdown,
+}
+
+// What a row stands for. Per meaning rows list that meaning's examples in one cell,
+// per example rows repeat the meaning for each of its examples.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub enum ExportRows {
+    #[default]
+    Meaning,
+    Example,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ImportReport {
+    // added to the lookup queue
+    pub queued: Vec<String>,
+    // already in the vocabulary book
+    pub skipped: Vec<String>,
+}
+
+// separates several examples or translations inside one cell
+const CELL_SEPARATOR: &str = " / ";
+
+fn english(langs: &[Lang]) -> String {
+    langs
+        .iter()
+        .find_map(|l| match l {
+            Lang::English(s) => Some(s.clone()),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn translation(langs: &[Lang]) -> String {
+    langs
+        .iter()
+        .filter_map(|l| match l {
+            Lang::English(_) => None,
+            Lang::Chinese(s)
+            | Lang::Spanish(s)
+            | Lang::Japanese(s)
+            | Lang::Korean(s)
+            | Lang::German(s)
+            | Lang::French(s)
+            | Lang::Portuguese(s) => Some(s.as_str()),
+        })
+        .collect::<Vec<&str>>()
+        .join(CELL_SEPARATOR)
+}
+
+fn header(rows: &ExportRows) -> Vec<&'static str> {
+    match rows {
+        ExportRows::Meaning => vec!["query", "pos", "meaning", "translation", "examples"],
+        ExportRows::Example => vec![
+            "query",
+            "pos",
+            "meaning",
+            "translation",
+            "example",
+            "example_translation",
+        ],
+    }
+}
+
+fn table(entries: &[Entry], rows: &ExportRows) -> Vec<Vec<String>> {
+    let mut table = Vec::<Vec<String>>::new();
+
+    for entry in entries {
+        for meaning in &entry.meanings {
+            for item in &meaning.meanings {
+                let row = vec![
+                    entry.query.clone(),
+                    meaning.pos.clone(),
+                    english(&item.meaning),
+                    translation(&item.meaning),
+                ];
+
+                match rows {
+                    ExportRows::Meaning => {
+                        let examples = item
+                            .examples
+                            .iter()
+                            .map(|example| english(example))
+                            .collect::<Vec<String>>()
+                            .join(CELL_SEPARATOR);
+                        table.push([row, vec![examples]].concat());
+                    }
+                    ExportRows::Example => {
+                        if item.examples.is_empty() {
+                            table.push([row.clone(), vec![String::new(), String::new()]].concat());
+                        }
+                        for example in &item.examples {
+                            table.push(
+                                [row.clone(), vec![english(example), translation(example)]].concat(),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    table
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+pub fn export_entries(
+    entries: &[Entry],
+    path: &Path,
+    format: &ExportFormat,
+    rows: &ExportRows,
+) -> Result<usize> {
+    let header = header(rows);
+    let table = table(entries, rows);
+
+    match format {
+        ExportFormat::Csv | ExportFormat::Tsv => {
+            let delimiter = match format {
+                ExportFormat::Tsv => b'\t',
+                _ => b',',
+            };
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .from_path(path)?;
+            writer.write_record(&header)?;
+            for row in &table {
+                writer.write_record(row)?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Markdown => {
+            let mut file = File::create(path)?;
+            writeln!(file, "| {} |", header.join(" | "))?;
+            writeln!(file, "|{}", " --- |".repeat(header.len()))?;
+            for row in &table {
+                let cells: Vec<String> = row.iter().map(|cell| markdown_cell(cell)).collect();
+                writeln!(file, "| {} |", cells.join(" | "))?;
+            }
+            file.flush()?;
+        }
+    }
+
+    Ok(table.len())
+}
+
+// Reads the words of a word list: one word per line, or the first column of a
+// .csv/.tsv file such as one written by export_entries. Blank lines and lines
+// starting with # are skipped, duplicates are dropped keeping the first.
+pub fn read_word_list(path: &Path) -> Result<Vec<String>> {
+    let delimiter = match path.extension().and_then(|e| e.to_str()) {
+        Some("csv") => Some(b','),
+        Some("tsv") => Some(b'\t'),
+        _ => None,
+    };
+
+    let mut lines = Vec::<String>::new();
+
+    match delimiter {
+        Some(delimiter) => {
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .has_headers(false)
+                .flexible(true)
+                .from_path(path)?;
+            for (index, record) in reader.records().enumerate() {
+                let record = record?;
+                let first = record.get(0).unwrap_or_default();
+                if index == 0 && first == "query" {
+                    continue;
+                }
+                lines.push(first.to_string());
+            }
+        }
+        None => {
+            let content = std::fs::read_to_string(path)?;
+            lines.extend(content.lines().map(|line| line.to_string()));
+        }
+    }
+
+    let mut words = Vec::<String>::new();
+    for line in lines {
+        let word = line.trim().to_lowercase();
+        if word.is_empty() || word.starts_with('#') || words.contains(&word) {
+            continue;
+        }
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::Annotations;
+    use crate::entry::{Item, Meaning};
+
+    fn entry(query: &str, items: Vec<Item>) -> Entry {
+        Entry {
+            query: query.to_string(),
+            meanings: vec![Meaning {
+                pos: "noun".to_string(),
+                meanings: items,
+            }],
+            source_lang: None,
+            annotations: Annotations::default(),
+        }
+    }
+
+    fn item(meaning: &str, translation: &str, examples: &[(&str, &str)]) -> Item {
+        Item {
+            meaning: vec![
+                Lang::English(meaning.to_string()),
+                Lang::Chinese(translation.to_string()),
+            ],
+            examples: examples
+                .iter()
+                .map(|(english, chinese)| {
+                    vec![
+                        Lang::English(english.to_string()),
+                        Lang::Chinese(chinese.to_string()),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            entry(
+                "serendipity",
+                vec![
+                    item(
+                        "Luck, in a happy way.",
+                        "机缘凑巧",
+                        &[("It was serendipity, \"pure\" luck.", "纯属巧合。"), ("A | B", "甲")],
+                    ),
+                    item("The knack of lucky finds.", "意外发现的本领", &[]),
+                ],
+            ),
+            entry("ephemeral", vec![item("Lasting a very short time.", "短暂的", &[])]),
+        ]
+    }
+
+    fn write(dir: &tempfile::TempDir, name: &str, content: &str) -> std::path::PathBuf {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn csv_export_round_trips_through_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("words.csv");
+
+        let rows = export_entries(&entries(), &path, &ExportFormat::Csv, &ExportRows::Meaning).unwrap();
+        assert_eq!(rows, 3);
+
+        // the header is skipped and the word of each further meaning only counted once
+        assert_eq!(read_word_list(&path).unwrap(), vec!["serendipity", "ephemeral"]);
+
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let records: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            records[0].iter().collect::<Vec<&str>>(),
+            vec![
+                "serendipity",
+                "noun",
+                "Luck, in a happy way.",
+                "机缘凑巧",
+                "It was serendipity, \"pure\" luck. / A | B"
+            ]
+        );
+    }
+
+    #[test]
+    fn tsv_export_per_example_round_trips_through_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("words.tsv");
+
+        let rows = export_entries(&entries(), &path, &ExportFormat::Tsv, &ExportRows::Example).unwrap();
+        // two examples, then one row each for the meanings without any
+        assert_eq!(rows, 4);
+
+        assert_eq!(read_word_list(&path).unwrap(), vec!["serendipity", "ephemeral"]);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines[0], "query\tpos\tmeaning\ttranslation\texample\texample_translation");
+        assert_eq!(lines[2], "serendipity\tnoun\tLuck, in a happy way.\t机缘凑巧\tA | B\t甲");
+        assert_eq!(lines[3], "serendipity\tnoun\tThe knack of lucky finds.\t意外发现的本领\t\t");
+    }
+
+    #[test]
+    fn markdown_export_escapes_cells() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("words.md");
+        let mut entries = entries();
+        entries[1].meanings[0].meanings[0].meaning[0] = Lang::English("Short\nlived.".to_string());
+
+        export_entries(&entries, &path, &ExportFormat::Markdown, &ExportRows::Meaning).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "| query | pos | meaning | translation | examples |");
+        assert_eq!(lines[1], "| --- | --- | --- | --- | --- |");
+        assert_eq!(
+            lines[2],
+            "| serendipity | noun | Luck, in a happy way. | 机缘凑巧 | It was serendipity, \"pure\" luck. / A \\| B |"
+        );
+        assert_eq!(lines[4], "| ephemeral | noun | Short lived. | 短暂的 |  |");
+    }
+
+    #[test]
+    fn plain_word_lists_skip_blanks_comments_and_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(
+            &dir,
+            "words.txt",
+            "Serendipity\n\n# from chapter one\nephemeral\r\nserendipity\n  Ephemeral  \nquery\n",
+        );
+
+        assert_eq!(
+            read_word_list(&path).unwrap(),
+            vec!["serendipity", "ephemeral", "query"]
+        );
+    }
+
+    #[test]
+    fn only_a_first_row_named_query_is_a_header() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = write(&dir, "words.csv", "serendipity,noun\nquery,noun\nephemeral\n");
+        assert_eq!(
+            read_word_list(&path).unwrap(),
+            vec!["serendipity", "query", "ephemeral"]
+        );
+
+        let path = write(&dir, "header.tsv", "query\tpos\nserendipity\tnoun\n");
+        assert_eq!(read_word_list(&path).unwrap(), vec!["serendipity"]);
+    }
+}
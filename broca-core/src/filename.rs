@@ -0,0 +1,98 @@
+use slugify::slugify;
+
+// Filenames in vocabulary/ and review/ encode the query reversibly: lowercase ASCII
+// letters, digits and '-' stay as they are, a space becomes '_', and every other byte
+// of the UTF-8 text is written as %XX with uppercase hex. No two queries share a name,
+// and no two names differ only in case, which matters on macOS and Windows.
+const MAX_STEM_BYTES: usize = 200;
+// Windows refuses these as filenames whatever the extension
+const RESERVED: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+fn encode(query: &str) -> String {
+    let mut stem = String::new();
+
+    for byte in query.bytes() {
+        match byte {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' => stem.push(byte as char),
+            b' ' => stem.push('_'),
+            _ => stem.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    if RESERVED.contains(&stem.as_str()) {
+        stem = format!("%{:02X}{}", stem.as_bytes()[0], &stem[1..]);
+    }
+
+    stem
+}
+
+// The stem for `query`, case insensitive like the cache. Names that would get too long
+// for the filesystem (a long phrase in a non-Latin script takes nine bytes per character)
+// keep an encoded prefix plus a hash of the whole query, the query itself is still
+// inside the file.
+pub fn entry_stem(query: &str) -> String {
+    let query = query.to_lowercase();
+    let stem = encode(&query);
+
+    if stem.len() <= MAX_STEM_BYTES {
+        return stem;
+    }
+
+    // cut on an escape boundary so the prefix still decodes
+    let mut cut = MAX_STEM_BYTES - 41;
+    while stem.as_bytes()[cut - 1] == b'%' || stem.as_bytes()[cut - 2] == b'%' {
+        cut -= 1;
+    }
+    let hash = sha1_smol::Sha1::from(&query).digest().to_string();
+
+    format!("{}~{}", &stem[..cut], hash)
+}
+
+pub fn entry_filename(query: &str) -> String {
+    format!("{}.json", entry_stem(query))
+}
+
+// The query a filename was made from, None for names this scheme didn't produce,
+// including the hashed form of very long queries.
+pub fn query_from_filename(filename: &str) -> Option<String> {
+    let stem = filename.strip_suffix(".json")?;
+    let mut bytes = Vec::<u8>::new();
+    let mut chars = stem.bytes();
+
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' => bytes.push(byte),
+            b'_' => bytes.push(b' '),
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+
+    let query = String::from_utf8(bytes).ok()?;
+
+    // only the canonical spelling, so each query has exactly one filename
+    if entry_stem(&query) != stem {
+        return None;
+    }
+
+    Some(query)
+}
+
+// How files were named before, slugify with '_' and hex for queries that slug to
+// nothing. Kept to find and rename files saved that way.
+pub fn legacy_entry_filename(query: &str) -> String {
+    let slug = slugify!(query, separator = "_");
+
+    if slug.is_empty() {
+        let hex: String = query.bytes().map(|b| format!("{:02x}", b)).collect();
+        return format!("{}.json", hex);
+    }
+
+    format!("{}.json", slug.as_str())
+}
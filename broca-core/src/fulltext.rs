@@ -0,0 +1,233 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::entry::{Entry, Lang};
+
+// The trigram tokenizer gives substring matches for any script, which the default
+// unicode61 tokenizer can't do for Chinese/Japanese text without word boundaries.
+// It needs at least three characters per query though, shorter ones fall back to LIKE.
+const MIN_MATCH_CHARS: usize = 3;
+const SNIPPET_CONTEXT_CHARS: usize = 30;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FullTextHit {
+    pub query: String,
+    // matched text with the hit wrapped in <b></b>
+    pub snippet: String,
+    // lower is better
+    pub rank: f64,
+}
+
+pub fn create_index(conn: &Connection) -> Result<()> {
+    conn.execute("CREATE VIRTUAL TABLE IF NOT EXISTS vocabulary_fts USING fts5(query, english, translation, examples, tokenize = 'trigram');", ())?;
+    Ok(())
+}
+
+fn text(lang: &Lang) -> (bool, &str) {
+    match lang {
+        Lang::English(s) => (true, s),
+        Lang::Chinese(s)
+        | Lang::Spanish(s)
+        | Lang::Japanese(s)
+        | Lang::Korean(s)
+        | Lang::German(s)
+        | Lang::French(s)
+        | Lang::Portuguese(s) => (false, s),
+    }
+}
+
+pub fn index_entry(conn: &Connection, entry: &Entry) -> Result<()> {
+    let mut english = Vec::<&str>::new();
+    let mut translation = Vec::<&str>::new();
+    let mut examples = Vec::<&str>::new();
+
+    for meaning in &entry.meanings {
+        for item in &meaning.meanings {
+            for lang in &item.meaning {
+                match text(lang) {
+                    (true, s) => english.push(s),
+                    (false, s) => translation.push(s),
+                }
+            }
+            for example in &item.examples {
+                for lang in example {
+                    examples.push(text(lang).1);
+                }
+            }
+        }
+    }
+
+    remove_entry(conn, &entry.query)?;
+    conn.execute(
+        "INSERT INTO vocabulary_fts(query, english, translation, examples) VALUES (?1, ?2, ?3, ?4);",
+        (
+            entry.query.to_lowercase(),
+            english.join("\n"),
+            translation.join("\n"),
+            examples.join("\n"),
+        ),
+    )?;
+
+    Ok(())
+}
+
+pub fn remove_entry(conn: &Connection, query: &str) -> Result<()> {
+    conn.execute("DELETE FROM vocabulary_fts WHERE query = ?1;", [query.to_lowercase()])?;
+    Ok(())
+}
+
+pub fn search(conn: &Connection, query: &str, limit: i64) -> Result<Vec<FullTextHit>> {
+    let query = query.trim();
+
+    if query.chars().count() < MIN_MATCH_CHARS {
+        return search_short(conn, query, limit);
+    }
+
+    // quote the query as a single phrase so FTS5 operators in user input are taken literally
+    let phrase = format!("\"{}\"", query.replace('"', "\"\""));
+
+    // headword matches weigh the most, then definitions, then examples
+    let mut stmt = conn.prepare_cached(
+        "SELECT query, snippet(vocabulary_fts, -1, '<b>', '</b>', '…', 16), bm25(vocabulary_fts, 10.0, 5.0, 5.0, 1.0) AS score
+        FROM vocabulary_fts WHERE vocabulary_fts MATCH ?1 ORDER BY score LIMIT ?2;",
+    )?;
+    let hit_iter = stmt.query_map((phrase, limit), |row| {
+        rusqlite::Result::Ok(FullTextHit {
+            query: row.get(0)?,
+            snippet: row.get(1)?,
+            rank: row.get(2)?,
+        })
+    })?;
+
+    let mut result = Vec::<FullTextHit>::new();
+    for hit in hit_iter {
+        result.push(hit?);
+    }
+
+    Ok(result)
+}
+
+fn search_short(conn: &Connection, query: &str, limit: i64) -> Result<Vec<FullTextHit>> {
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let pattern = format!(
+        "%{}%",
+        query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT query, english, translation, examples FROM vocabulary_fts
+        WHERE query LIKE ?1 ESCAPE '\\' OR english LIKE ?1 ESCAPE '\\' OR translation LIKE ?1 ESCAPE '\\' OR examples LIKE ?1 ESCAPE '\\'
+        LIMIT ?2;",
+    )?;
+    let row_iter = stmt.query_map((pattern, limit), |row| {
+        rusqlite::Result::Ok([
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+        ])
+    })?;
+
+    let mut result = Vec::<FullTextHit>::new();
+    for row in row_iter {
+        let columns = row?;
+        // rank by the first column that contains the query, same weighting as above
+        for (rank, column) in columns.iter().enumerate() {
+            if let Some(snippet) = snippet(column, query) {
+                result.push(FullTextHit {
+                    query: columns[0].clone(),
+                    snippet,
+                    rank: rank as f64,
+                });
+                break;
+            }
+        }
+    }
+    result.sort_by(|a, b| a.rank.partial_cmp(&b.rank).unwrap_or(std::cmp::Ordering::Equal));
+
+    Ok(result)
+}
+
+fn snippet(text: &str, query: &str) -> Option<String> {
+    let lower = text.to_lowercase();
+    let needle = query.to_lowercase();
+
+    // lowercasing can change byte lengths, so locate the hit by characters
+    let start = lower.find(&needle)?;
+    let start_char = lower[..start].chars().count();
+    let needle_chars = needle.chars().count();
+
+    let chars: Vec<char> = text.chars().collect();
+    if start_char + needle_chars > chars.len() {
+        return Some(text.to_string());
+    }
+
+    let from = start_char.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let to = (start_char + needle_chars + SNIPPET_CONTEXT_CHARS).min(chars.len());
+
+    let mut result = String::new();
+    if from > 0 {
+        result.push('…');
+    }
+    result.extend(&chars[from..start_char]);
+    result.push_str("<b>");
+    result.extend(&chars[start_char..start_char + needle_chars]);
+    result.push_str("</b>");
+    result.extend(&chars[start_char + needle_chars..to]);
+    if to < chars.len() {
+        result.push('…');
+    }
+
+    Some(result.replace('\n', " "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::Annotations;
+    use crate::entry::{Item, Meaning};
+
+    fn entry(query: &str) -> Entry {
+        Entry {
+            query: query.to_string(),
+            meanings: vec![Meaning {
+                pos: String::from("noun"),
+                meanings: vec![Item {
+                    meaning: vec![Lang::English(String::from("The capital of France."))],
+                    examples: vec![vec![Lang::English(String::from("We flew to Paris."))]],
+                }],
+            }],
+            source_lang: None,
+            annotations: Annotations::default(),
+        }
+    }
+
+    fn rows(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM vocabulary_fts;", (), |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn reindexing_a_mixed_case_entry_keeps_one_row() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_index(&conn).unwrap();
+
+        index_entry(&conn, &entry("Paris")).unwrap();
+        index_entry(&conn, &entry("Paris")).unwrap();
+        assert_eq!(rows(&conn), 1);
+
+        let hits = search(&conn, "capital", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].query, "paris");
+
+        remove_entry(&conn, "Paris").unwrap();
+        assert_eq!(rows(&conn), 0);
+    }
+}
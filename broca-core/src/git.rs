@@ -0,0 +1,368 @@
+use anyhow::{anyhow, Result};
+use git2::{
+    build::CheckoutBuilder, Commit, IndexAddOption, MergeAnalysis, Oid, PushOptions,
+    RemoteCallbacks, Repository, RepositoryInitOptions, Signature,
+};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// The folders whose files are versioned, cache.db and audio/ can be made again.
+const TRACKED: [&str; 3] = ["vocabulary", "review", "collections"];
+const REMOTE: &str = "origin";
+// the stage bits of an index entry's flags, non-zero for the sides of a conflict
+const STAGE_MASK: u16 = 0x3000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct GitConfig {
+    pub enabled: bool,
+    // path or file:// URL of a bare repository to pull from and push to
+    pub remote: Option<String>,
+}
+
+// A commit that changed a word's file, newest first in history lists.
+#[derive(Debug, Serialize, Clone)]
+pub struct Revision {
+    pub commit: String,
+    pub summary: String,
+    // seconds since the epoch
+    pub time: i64,
+    // the commit deleted the word
+    pub deleted: bool,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct PullReport {
+    // commits that came in from the remote
+    pub received: usize,
+    // files written next to a word both sides had changed, see conflict::Conflict
+    pub conflicted_copies: Vec<String>,
+}
+
+// The workspace as a git repository. Clones share the lock, so commits from the
+// queue workers and the app don't race for the index.
+#[derive(Clone)]
+pub struct GitRepo {
+    workdir: PathBuf,
+    remote: Option<String>,
+    lock: Arc<Mutex<()>>,
+}
+
+fn signature(repo: &Repository) -> Result<Signature<'static>> {
+    match repo.signature() {
+        Ok(signature) => Ok(signature),
+        // no user.name / user.email configured anywhere
+        Err(_) => Ok(Signature::now("Broca", "broca@localhost")?),
+    }
+}
+
+fn head_commit(repo: &Repository) -> Result<Option<Commit<'_>>> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_commit()?)),
+        Err(error) if error.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+// refs/heads/<name> of the checked out branch, also before its first commit.
+fn head_branch(repo: &Repository) -> Result<String> {
+    match repo.find_reference("HEAD")?.symbolic_target() {
+        Some(target) => Ok(target.to_string()),
+        None => Err(anyhow!("The workspace repository has no branch checked out.")),
+    }
+}
+
+fn blob_at(commit: &Commit, path: &Path) -> Result<Option<Oid>> {
+    match commit.tree()?.get_path(path) {
+        Ok(entry) => Ok(Some(entry.id())),
+        Err(error) if error.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+// `vocabulary/serendipity.json` -> `vocabulary/serendipity (conflicted copy 1a2b3c4).json`
+fn conflicted_copy_path(path: &str, commit: &Oid) -> String {
+    let (stem, extension) = match path.rsplit_once('.') {
+        Some((stem, extension)) => (stem, format!(".{}", extension)),
+        None => (path, String::new()),
+    };
+    let short = commit.to_string();
+    format!("{} (conflicted copy {}){}", stem, &short[..7], extension)
+}
+
+impl GitRepo {
+    // Opens the repository at `workdir`, creating it when there is none yet.
+    pub fn open(workdir: &Path, config: &GitConfig) -> Result<GitRepo> {
+        if Repository::open(workdir).is_err() {
+            let mut options = RepositoryInitOptions::new();
+            options.initial_head("main");
+            Repository::init_opts(workdir, &options)?;
+            eprintln!("Created a git repository in {}", workdir.display());
+        }
+
+        Ok(GitRepo {
+            workdir: workdir.to_path_buf(),
+            remote: config.remote.clone(),
+            lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    fn repo(&self) -> Result<Repository> {
+        Ok(Repository::open(&self.workdir)?)
+    }
+
+    fn commit_index(&self, repo: &Repository, message: &str) -> Result<Option<String>> {
+        let mut index = repo.index()?;
+        let tree_id = index.write_tree()?;
+        let parent = head_commit(repo)?;
+
+        if let Some(parent) = &parent {
+            if parent.tree_id() == tree_id {
+                return Ok(None);
+            }
+        }
+
+        let tree = repo.find_tree(tree_id)?;
+        let signature = signature(repo)?;
+        let parents: Vec<&Commit> = parent.iter().collect();
+        let id = repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+
+        Ok(Some(id.to_string()))
+    }
+
+    // Commits the current state of `paths` (relative to the workspace, deleted files
+    // are removed), None when they match the last commit already.
+    pub fn commit(&self, paths: &[String], message: &str) -> Result<Option<String>> {
+        let _guard = self.lock.lock().unwrap();
+        let repo = self.repo()?;
+        let mut index = repo.index()?;
+
+        for path in paths {
+            let path = Path::new(path);
+            if self.workdir.join(path).exists() {
+                index.add_path(path)?;
+            } else if index.get_path(path, 0).is_some() {
+                index.remove_path(path)?;
+            }
+        }
+        index.write()?;
+
+        self.commit_index(&repo, message)
+    }
+
+    // Commits whatever changed in the tracked folders without going through the app,
+    // e.g. review files or edits by hand.
+    pub fn commit_all(&self, message: &str) -> Result<Option<String>> {
+        let _guard = self.lock.lock().unwrap();
+        let repo = self.repo()?;
+        let mut index = repo.index()?;
+
+        index.add_all(TRACKED.iter(), IndexAddOption::DEFAULT, None)?;
+        index.update_all(TRACKED.iter(), None)?;
+        index.write()?;
+
+        self.commit_index(&repo, message)
+    }
+
+    fn remote<'r>(&self, repo: &'r Repository) -> Result<git2::Remote<'r>> {
+        let url = match &self.remote {
+            Some(url) => url,
+            None => return Err(anyhow!("No git remote configured.")),
+        };
+
+        match repo.find_remote(REMOTE) {
+            Ok(remote) if remote.url() == Some(url.as_str()) => Ok(remote),
+            Ok(_) => {
+                repo.remote_set_url(REMOTE, url)?;
+                Ok(repo.find_remote(REMOTE)?)
+            }
+            Err(_) => Ok(repo.remote(REMOTE, url)?),
+        }
+    }
+
+    // Fetches the remote's copy of the current branch and merges it in. Where both sides
+    // changed the same word, ours stays and theirs is saved as a conflicted copy for
+    // resolve_conflict; a word edited on one side and deleted on the other is kept.
+    // Commit local changes first, the working tree is overwritten.
+    pub fn pull(&self) -> Result<PullReport> {
+        let _guard = self.lock.lock().unwrap();
+        let repo = self.repo()?;
+        let branch = head_branch(&repo)?;
+        let name = branch.trim_start_matches("refs/heads/");
+        let tracking = format!("refs/remotes/{}/{}", REMOTE, name);
+
+        self.remote(&repo)?
+            .fetch(&[format!("+{}:{}", branch, tracking)], None, None)?;
+
+        let theirs = match repo.find_reference(&tracking) {
+            Ok(reference) => reference.peel_to_commit()?,
+            // the remote doesn't have the branch yet
+            Err(_) => return Ok(PullReport::default()),
+        };
+
+        let mut report = PullReport::default();
+        let ours = match head_commit(&repo)? {
+            Some(ours) => ours,
+            None => {
+                repo.reference(&branch, theirs.id(), true, "pull: initial")?;
+                repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+                return Ok(report);
+            }
+        };
+
+        let annotated = repo.find_annotated_commit(theirs.id())?;
+        let (analysis, _) = repo.merge_analysis(&[&annotated])?;
+        if analysis.contains(MergeAnalysis::ANALYSIS_UP_TO_DATE) {
+            return Ok(report);
+        }
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push(theirs.id())?;
+        revwalk.hide(ours.id())?;
+        report.received = revwalk.count();
+
+        if analysis.contains(MergeAnalysis::ANALYSIS_FASTFORWARD) {
+            repo.find_reference(&branch)?
+                .set_target(theirs.id(), "pull: fast-forward")?;
+            repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+            return Ok(report);
+        }
+
+        let mut index = repo.merge_commits(&ours, &theirs, None)?;
+        let conflicts = index
+            .conflicts()?
+            .collect::<std::result::Result<Vec<_>, git2::Error>>()?;
+
+        for conflict in conflicts {
+            let path = match [&conflict.our, &conflict.their, &conflict.ancestor]
+                .into_iter()
+                .flatten()
+                .next()
+            {
+                Some(entry) => String::from_utf8(entry.path.clone())?,
+                None => continue,
+            };
+            // drops all three stages
+            index.remove_path(Path::new(&path))?;
+
+            match (conflict.our, conflict.their) {
+                (Some(mut our), Some(mut their)) => {
+                    our.flags &= !STAGE_MASK;
+                    index.add(&our)?;
+                    // both end up in the cache, as a conflict or as the union of members
+                    if path.starts_with("vocabulary/") || path.starts_with("collections/") {
+                        let copy = conflicted_copy_path(&path, &theirs.id());
+                        their.flags &= !STAGE_MASK;
+                        their.path = copy.clone().into_bytes();
+                        index.add(&their)?;
+                        report.conflicted_copies.push(copy);
+                    }
+                }
+                (Some(mut entry), None) | (None, Some(mut entry)) => {
+                    entry.flags &= !STAGE_MASK;
+                    index.add(&entry)?;
+                }
+                (None, None) => {}
+            }
+        }
+
+        let tree = repo.find_tree(index.write_tree_to(&repo)?)?;
+        let signature = signature(&repo)?;
+        let message = format!("Merge {} from {}", name, REMOTE);
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &message,
+            &tree,
+            &[&ours, &theirs],
+        )?;
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+
+        Ok(report)
+    }
+
+    // Pushes the current branch, which fails when the remote has commits we haven't
+    // pulled yet.
+    pub fn push(&self) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let repo = self.repo()?;
+        let branch = head_branch(&repo)?;
+        let rejected = RefCell::new(None);
+
+        {
+            let mut callbacks = RemoteCallbacks::new();
+            callbacks.push_update_reference(|reference, status| {
+                if let Some(status) = status {
+                    *rejected.borrow_mut() = Some(format!("{}: {}", reference, status));
+                }
+                Ok(())
+            });
+            let mut options = PushOptions::new();
+            options.remote_callbacks(callbacks);
+
+            self.remote(&repo)?
+                .push(&[format!("{}:{}", branch, branch)], Some(&mut options))?;
+        }
+
+        match rejected.into_inner() {
+            Some(message) => Err(anyhow!("Push rejected, pull first. {}", message)),
+            None => Ok(()),
+        }
+    }
+
+    // The commits reachable from HEAD that added, changed or deleted `path`.
+    pub fn history(&self, path: &str) -> Result<Vec<Revision>> {
+        let repo = self.repo()?;
+        let path = Path::new(path);
+        let mut revisions = Vec::new();
+
+        if head_commit(&repo)?.is_none() {
+            return Ok(revisions);
+        }
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+        revwalk.push_head()?;
+
+        for id in revwalk {
+            let commit = repo.find_commit(id?)?;
+            let blob = blob_at(&commit, path)?;
+
+            let mut changed = true;
+            for parent in commit.parents() {
+                if blob_at(&parent, path)? == blob {
+                    changed = false;
+                }
+            }
+            if commit.parent_count() == 0 && blob.is_none() {
+                changed = false;
+            }
+
+            if changed {
+                revisions.push(Revision {
+                    commit: commit.id().to_string(),
+                    summary: commit.summary().unwrap_or_default().to_string(),
+                    time: commit.time().seconds(),
+                    deleted: blob.is_none(),
+                });
+            }
+        }
+
+        Ok(revisions)
+    }
+
+    // The content of `path` as of `commit` (a full or abbreviated id), None when the
+    // file didn't exist there.
+    pub fn file_at(&self, commit: &str, path: &str) -> Result<Option<String>> {
+        let repo = self.repo()?;
+        let commit = repo.revparse_single(commit)?.peel_to_commit()?;
+
+        match blob_at(&commit, Path::new(path))? {
+            Some(id) => Ok(Some(String::from_utf8(repo.find_blob(id)?.content().to_vec())?)),
+            None => Ok(None),
+        }
+    }
+}
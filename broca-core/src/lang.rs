@@ -0,0 +1,151 @@
+use whatlang::{Detector, Lang as DetectedLang, Script};
+
+use crate::state::TargetLang;
+
+const MIN_CONFIDENCE: f64 = 0.3;
+
+// Works out whether a query is written in one of the target languages rather than English,
+// in which case it should be looked up in reverse.
+// Non-Latin scripts are unambiguous enough on their own. For Latin script the detector only
+// chooses between English and the active target languages, and we require either some
+// confidence or letters English never uses, since short words like "pan" or "court" are
+// valid in both. Accents alone don't count: English borrows "café", "naïve" or "résumé",
+// and the detector all but decides on the accents. The confidence is that of the query
+// with them taken off.
+pub fn detect_source_lang(query: &str, target_langs: &[TargetLang]) -> Option<TargetLang> {
+    let script = whatlang::detect_script(query)?;
+
+    match script {
+        Script::Hangul => return Some(TargetLang::Korean),
+        Script::Hiragana | Script::Katakana => return Some(TargetLang::Japanese),
+        // kanji only queries are ambiguous, prefer whichever the user is learning
+        Script::Mandarin => {
+            return match target_langs.iter().find(|l| matches!(l, TargetLang::Chinese | TargetLang::Japanese)) {
+                Some(TargetLang::Japanese) => Some(TargetLang::Japanese),
+                _ => Some(TargetLang::Chinese),
+            }
+        }
+        Script::Latin => {}
+        _ => return None,
+    }
+
+    let candidates: Vec<(DetectedLang, &TargetLang)> = target_langs
+        .iter()
+        .filter_map(|l| match l {
+            TargetLang::Spanish => Some((DetectedLang::Spa, l)),
+            TargetLang::German => Some((DetectedLang::Deu, l)),
+            TargetLang::French => Some((DetectedLang::Fra, l)),
+            TargetLang::Portuguese => Some((DetectedLang::Por, l)),
+            _ => None,
+        })
+        .collect();
+
+    if candidates.is_empty() {
+        return None;
+    }
+
+    let mut allowlist = vec![DetectedLang::Eng];
+    allowlist.extend(candidates.iter().map(|(detected, _)| *detected));
+
+    let info = Detector::with_allowlist(allowlist.clone()).detect(query)?;
+
+    let (_, target_lang) = candidates
+        .iter()
+        .find(|(detected, _)| *detected == info.lang())?;
+
+    let has_foreign_letters = query.chars().any(|c| matches!(c, 'ñ' | 'Ñ' | 'ß' | '¿' | '¡'));
+
+    let unaccented = remove_accents(query);
+    let confidence = if unaccented == query {
+        info.confidence()
+    } else {
+        match Detector::with_allowlist(allowlist).detect(&unaccented) {
+            Some(unaccented_info) if unaccented_info.lang() == info.lang() => {
+                unaccented_info.confidence()
+            }
+            _ => 0.0,
+        }
+    };
+
+    if confidence >= MIN_CONFIDENCE || has_foreign_letters {
+        return Some((*target_lang).clone());
+    }
+
+    None
+}
+
+// Takes the accents English loanwords keep off their letters.
+fn remove_accents(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+            'ç' => 'c',
+            'è' | 'é' | 'ê' | 'ë' => 'e',
+            'ì' | 'í' | 'î' | 'ï' => 'i',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' => 'o',
+            'ù' | 'ú' | 'û' | 'ü' => 'u',
+            'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' => 'A',
+            'Ç' => 'C',
+            'È' | 'É' | 'Ê' | 'Ë' => 'E',
+            'Ì' | 'Í' | 'Î' | 'Ï' => 'I',
+            'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' => 'O',
+            'Ù' | 'Ú' | 'Û' | 'Ü' => 'U',
+            _ => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_loanwords_are_not_looked_up_in_reverse() {
+        for target_langs in [
+            vec![TargetLang::French],
+            vec![TargetLang::Spanish],
+            vec![TargetLang::French, TargetLang::Spanish],
+        ] {
+            for word in ["café", "naïve", "résumé", "déjà vu"] {
+                assert_eq!(detect_source_lang(word, &target_langs), None, "{} {:?}", word, target_langs);
+            }
+        }
+    }
+
+    #[test]
+    fn words_valid_in_both_stay_english() {
+        assert_eq!(detect_source_lang("pan", &[TargetLang::Spanish]), None);
+        assert_eq!(detect_source_lang("pan", &[TargetLang::French]), None);
+        assert_eq!(detect_source_lang("court", &[TargetLang::French]), None);
+        assert_eq!(detect_source_lang("court", &[TargetLang::Spanish, TargetLang::French]), None);
+        assert_eq!(detect_source_lang("serendipity", &[TargetLang::Spanish]), None);
+    }
+
+    #[test]
+    fn target_language_phrases_are_looked_up_in_reverse() {
+        assert_eq!(
+            detect_source_lang("où est la gare", &[TargetLang::French]),
+            Some(TargetLang::French)
+        );
+        assert_eq!(
+            detect_source_lang("¿dónde está la biblioteca?", &[TargetLang::Spanish]),
+            Some(TargetLang::Spanish)
+        );
+    }
+
+    #[test]
+    fn letters_english_never_uses_count_on_their_own() {
+        assert_eq!(detect_source_lang("niño", &[TargetLang::Spanish]), Some(TargetLang::Spanish));
+        assert_eq!(detect_source_lang("straße", &[TargetLang::German]), Some(TargetLang::German));
+        // only among the active target languages
+        assert_eq!(detect_source_lang("niño", &[TargetLang::Chinese]), None);
+    }
+
+    #[test]
+    fn other_scripts_decide_by_themselves() {
+        assert_eq!(detect_source_lang("사랑", &[]), Some(TargetLang::Korean));
+        assert_eq!(detect_source_lang("ありがとう", &[]), Some(TargetLang::Japanese));
+        assert_eq!(detect_source_lang("学习", &[]), Some(TargetLang::Chinese));
+        assert_eq!(detect_source_lang("学習", &[TargetLang::Japanese]), Some(TargetLang::Japanese));
+    }
+}
@@ -0,0 +1,29 @@
+// Everything Broca does that isn't UI: the workspace and its cache.db, dictionary
+// providers, text to speech, reviews, import/export and usage accounting. Front-ends
+// hold a state::State and call its methods.
+
+pub mod anki;
+pub mod annotations;
+pub mod anthropic;
+pub mod collection;
+pub mod conflict;
+pub mod db;
+pub mod entry;
+pub mod export;
+pub mod filename;
+pub mod fulltext;
+pub mod git;
+pub mod lang;
+pub mod merge;
+pub mod migrations;
+pub mod openai;
+pub mod provider;
+pub mod queue;
+pub mod repair;
+pub mod replay;
+pub mod review;
+pub mod schema;
+pub mod state;
+pub mod stream;
+pub mod tts;
+pub mod watch;
@@ -0,0 +1,294 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::mem::discriminant;
+
+use crate::annotations::merge_annotations;
+use crate::entry::{Entry, Item, Lang, Meaning};
+
+// Meanings whose English definitions share at least this much of their vocabulary
+// (Jaccard index over words) are considered the same sense.
+const SIMILARITY_THRESHOLD: f64 = 0.5;
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct MergeReport {
+    // English definitions that were not in the saved entry
+    pub added_meanings: Vec<String>,
+    // English definitions the new lookup didn't return, kept from the saved entry
+    pub kept_meanings: Vec<String>,
+    pub matched_meanings: usize,
+    pub added_examples: usize,
+    pub added_translations: usize,
+}
+
+fn english(langs: &[Lang]) -> String {
+    langs
+        .iter()
+        .find_map(|l| match l {
+            Lang::English(s) => Some(s.trim().to_lowercase()),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+pub fn similarity(a: &str, b: &str) -> f64 {
+    if a.trim().eq_ignore_ascii_case(b.trim()) {
+        return 1.0;
+    }
+
+    let a = words(a);
+    let b = words(b);
+
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+fn normalize_pos(pos: &str) -> String {
+    pos.trim().to_lowercase()
+}
+
+// Folds examples and translations of `from` into `into`, returning how many of each were added.
+fn merge_item(into: &mut Item, from: Item) -> (usize, usize) {
+    let mut added_examples = 0;
+    let mut added_translations = 0;
+
+    for lang in from.meaning {
+        if !into.meaning.iter().any(|l| discriminant(l) == discriminant(&lang)) {
+            into.meaning.push(lang);
+            added_translations += 1;
+        }
+    }
+
+    for example in from.examples {
+        let example_english = english(&example);
+        if !into.examples.iter().any(|e| english(e) == example_english) {
+            into.examples.push(example);
+            added_examples += 1;
+        }
+    }
+
+    (added_examples, added_translations)
+}
+
+// Merges a fresh lookup into a saved entry. The saved entry is the base, so everything the
+// user accumulated on it survives; fresh senses are matched to saved ones by part of speech
+// and definition similarity, matched senses get the union of their examples.
+pub fn merge_entries(existing: Entry, fresh: Entry) -> (Entry, MergeReport) {
+    let mut merged = existing;
+    let mut report = MergeReport::default();
+
+    // (meaning index, item index) of every saved item, and whether a fresh item claimed it
+    let saved: Vec<(usize, usize)> = merged
+        .meanings
+        .iter()
+        .enumerate()
+        .flat_map(|(mi, m)| (0..m.meanings.len()).map(move |ii| (mi, ii)))
+        .collect();
+    let mut claimed = vec![false; saved.len()];
+
+    let fresh_items: Vec<(String, Item)> = fresh
+        .meanings
+        .into_iter()
+        .flat_map(|m| {
+            let pos = m.pos;
+            m.meanings.into_iter().map(move |item| (pos.clone(), item))
+        })
+        .collect();
+
+    // score every fresh/saved pair with the same part of speech, best pairs first
+    let mut candidates = Vec::<(f64, usize, usize)>::new();
+    for (fi, (pos, item)) in fresh_items.iter().enumerate() {
+        let fresh_english = english(&item.meaning);
+        for (si, (mi, ii)) in saved.iter().enumerate() {
+            let meaning = &merged.meanings[*mi];
+            if normalize_pos(&meaning.pos) != normalize_pos(pos) {
+                continue;
+            }
+            let score = similarity(&fresh_english, &english(&meaning.meanings[*ii].meaning));
+            if score >= SIMILARITY_THRESHOLD {
+                candidates.push((score, fi, si));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut assignment: Vec<Option<usize>> = vec![None; fresh_items.len()];
+    for (_, fi, si) in candidates {
+        if assignment[fi].is_none() && !claimed[si] {
+            assignment[fi] = Some(si);
+            claimed[si] = true;
+        }
+    }
+
+    for ((pos, item), target) in fresh_items.into_iter().zip(assignment) {
+        match target {
+            Some(si) => {
+                let (mi, ii) = saved[si];
+                let (examples, translations) = merge_item(&mut merged.meanings[mi].meanings[ii], item);
+                report.matched_meanings += 1;
+                report.added_examples += examples;
+                report.added_translations += translations;
+            }
+            None => {
+                report.added_meanings.push(english(&item.meaning));
+                match merged
+                    .meanings
+                    .iter_mut()
+                    .find(|m| normalize_pos(&m.pos) == normalize_pos(&pos))
+                {
+                    Some(meaning) => meaning.meanings.push(item),
+                    None => merged.meanings.push(Meaning {
+                        pos,
+                        meanings: vec![item],
+                    }),
+                }
+            }
+        }
+    }
+
+    for (si, (mi, ii)) in saved.iter().enumerate() {
+        if !claimed[si] {
+            report
+                .kept_meanings
+                .push(english(&merged.meanings[*mi].meanings[*ii].meaning));
+        }
+    }
+
+    // a fresh lookup has none, but another saved version of the word may
+    merged.annotations = merge_annotations(merged.annotations, fresh.annotations);
+
+    (merged, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::Annotations;
+
+    fn english_only(text: &str) -> Vec<Lang> {
+        vec![Lang::English(text.to_string())]
+    }
+
+    fn item(definition: &str, examples: &[&str]) -> Item {
+        Item {
+            meaning: english_only(definition),
+            examples: examples.iter().map(|e| english_only(e)).collect(),
+        }
+    }
+
+    fn entry(pos: &str, items: Vec<Item>) -> Entry {
+        Entry {
+            query: "serendipity".to_string(),
+            meanings: vec![Meaning {
+                pos: pos.to_string(),
+                meanings: items,
+            }],
+            source_lang: None,
+            annotations: Annotations::default(),
+        }
+    }
+
+    fn examples(item: &Item) -> Vec<String> {
+        item.examples.iter().map(|e| english(e)).collect()
+    }
+
+    #[test]
+    fn similarity_is_the_share_of_common_words() {
+        assert_eq!(similarity("Happy chance find", "happy chance find."), 1.0);
+        // 2 of 4 words
+        assert_eq!(similarity("happy chance find", "happy chance event"), 0.5);
+        // 3 of 5 words
+        assert_eq!(similarity("a lucky chance find", "a lucky chance discovery"), 0.6);
+        // 2 of 5 words
+        assert_eq!(similarity("lucky happy find", "lucky happy event day"), 0.4);
+        assert_eq!(similarity("", "happy"), 0.0);
+    }
+
+    #[test]
+    fn definitions_at_the_threshold_are_the_same_sense() {
+        let saved = entry("noun", vec![item("happy chance find", &["Saved example."])]);
+        let fresh = entry("noun", vec![item("happy chance event", &["Fresh example."])]);
+
+        let (merged, report) = merge_entries(saved, fresh);
+
+        assert_eq!(report.matched_meanings, 1);
+        assert!(report.added_meanings.is_empty());
+        assert_eq!(merged.meanings[0].meanings.len(), 1);
+        assert_eq!(
+            examples(&merged.meanings[0].meanings[0]),
+            vec!["saved example.", "fresh example."]
+        );
+    }
+
+    #[test]
+    fn definitions_just_above_the_threshold_are_the_same_sense() {
+        let saved = entry("noun", vec![item("a lucky chance find", &[])]);
+        let fresh = entry("noun", vec![item("a lucky chance discovery", &[])]);
+
+        let (merged, report) = merge_entries(saved, fresh);
+
+        assert_eq!(report.matched_meanings, 1);
+        assert_eq!(merged.meanings[0].meanings.len(), 1);
+        // the saved wording wins
+        assert_eq!(english(&merged.meanings[0].meanings[0].meaning), "a lucky chance find");
+    }
+
+    #[test]
+    fn definitions_just_below_the_threshold_are_kept_apart() {
+        let saved = entry("noun", vec![item("lucky happy find", &[])]);
+        let fresh = entry("noun", vec![item("lucky happy event day", &[])]);
+
+        let (merged, report) = merge_entries(saved, fresh);
+
+        assert_eq!(report.matched_meanings, 0);
+        assert_eq!(report.added_meanings, vec!["lucky happy event day"]);
+        assert_eq!(report.kept_meanings, vec!["lucky happy find"]);
+        assert_eq!(merged.meanings[0].meanings.len(), 2);
+    }
+
+    #[test]
+    fn other_parts_of_speech_never_match() {
+        let saved = entry("noun", vec![item("happy chance find", &[])]);
+        let fresh = entry("adjective", vec![item("happy chance find", &[])]);
+
+        let (merged, report) = merge_entries(saved, fresh);
+
+        assert_eq!(report.matched_meanings, 0);
+        assert_eq!(merged.meanings.len(), 2);
+        assert_eq!(merged.meanings[1].pos, "adjective");
+    }
+
+    #[test]
+    fn user_added_examples_survive_a_requery() {
+        let definition = "The occurrence of events by chance in a happy way.";
+        let saved = entry(
+            "noun",
+            vec![item(definition, &["It was pure serendipity.", "My own sentence about it."])],
+        );
+        let fresh = entry(
+            "noun",
+            vec![item(definition, &["It was pure serendipity.", "A new sentence."])],
+        );
+
+        let (merged, report) = merge_entries(saved, fresh);
+
+        assert_eq!(report.added_examples, 1);
+        assert_eq!(
+            examples(&merged.meanings[0].meanings[0]),
+            vec![
+                "it was pure serendipity.",
+                "my own sentence about it.",
+                "a new sentence."
+            ]
+        );
+    }
+}
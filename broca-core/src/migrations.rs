@@ -0,0 +1,127 @@
+use anyhow::{anyhow, Result};
+use rusqlite::Connection;
+
+use crate::filename::legacy_entry_filename;
+use crate::{fulltext, queue};
+
+// Migration i takes cache.db from schema version i to i + 1, and PRAGMA user_version
+// records how far a database has got. Only ever append: a migration that has shipped
+// is already applied to someone's cache and won't run there again.
+const MIGRATIONS: &[fn(&Connection) -> Result<()>] =
+    &[initial, vocabulary_filename, vocabulary_files, annotations, collections];
+
+// Everything up to the first versioned release. Caches from before user_version was
+// tracked are at version 0 with some of these tables present, hence IF NOT EXISTS.
+fn initial(conn: &Connection) -> Result<()> {
+    conn.execute("CREATE TABLE IF NOT EXISTS vocabulary ( query TEXT UNIQUE, content TEXT NOT NULL, timestamp INT NOT NULL);", ())?;
+    conn.execute("CREATE TABLE IF NOT EXISTS openai_usage (  id INTEGER PRIMARY KEY, prompt_tokens INTEGER NOT NULL,completion_tokens INTEGER NOT NULL);", ())?;
+    conn.execute("INSERT INTO openai_usage (id, prompt_tokens, completion_tokens) SELECT 1, 0, 0 WHERE NOT EXISTS (SELECT 1 FROM openai_usage);", ())?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS query_index ON vocabulary (query COLLATE NOCASE);",
+        (),
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS timestamp_index ON vocabulary(timestamp);",
+        (),
+    )?;
+
+    conn.execute("CREATE TABLE IF NOT EXISTS review ( query TEXT UNIQUE, content TEXT NOT NULL, due INT NOT NULL, last_reviewed INT NOT NULL);", ())?;
+
+    conn.execute("CREATE INDEX IF NOT EXISTS due_index ON review(due);", ())?;
+
+    fulltext::create_index(conn)?;
+    queue::create_table(conn)?;
+
+    Ok(())
+}
+
+// Remembers which file of vocabulary/ a row was read from, so a file that disappears
+// can be matched to its row.
+fn vocabulary_filename(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE vocabulary ADD COLUMN filename TEXT;", ())?;
+
+    // rows from before this were saved under the filename their query had back then
+    let mut stmt = conn.prepare("SELECT query FROM vocabulary;")?;
+    let queries = stmt
+        .query_map((), |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    for query in queries {
+        conn.execute(
+            "UPDATE vocabulary SET filename = ?1 WHERE query = ?2;",
+            (legacy_entry_filename(&query), &query),
+        )?;
+    }
+
+    conn.execute("CREATE INDEX IF NOT EXISTS filename_index ON vocabulary(filename);", ())?;
+
+    Ok(())
+}
+
+// Every file of vocabulary/ by name, several of them holding the same query is a sync
+// conflict. The vocabulary table keeps the one version that's shown.
+fn vocabulary_files(conn: &Connection) -> Result<()> {
+    conn.execute("CREATE TABLE IF NOT EXISTS vocabulary_files ( filename TEXT PRIMARY KEY, query TEXT NOT NULL, timestamp INT NOT NULL);", ())?;
+    conn.execute("CREATE INDEX IF NOT EXISTS vocabulary_files_query_index ON vocabulary_files(query);", ())?;
+    conn.execute("INSERT OR IGNORE INTO vocabulary_files(filename, query, timestamp) SELECT filename, query, timestamp FROM vocabulary WHERE filename IS NOT NULL;", ())?;
+
+    Ok(())
+}
+
+// The parts of each word's annotations that lists are filtered and sorted by. No file
+// had annotations before this, so there is nothing to backfill.
+fn annotations(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE vocabulary ADD COLUMN starred INT NOT NULL DEFAULT 0;", ())?;
+    conn.execute("ALTER TABLE vocabulary ADD COLUMN added INT;", ())?;
+    conn.execute("CREATE INDEX IF NOT EXISTS starred_index ON vocabulary(starred);", ())?;
+    conn.execute("CREATE TABLE IF NOT EXISTS tags ( query TEXT NOT NULL, tag TEXT NOT NULL, PRIMARY KEY (query, tag));", ())?;
+    conn.execute("CREATE INDEX IF NOT EXISTS tag_index ON tags(tag COLLATE NOCASE);", ())?;
+
+    Ok(())
+}
+
+// Files of collections/ and their members, see collection::Collection. Like
+// vocabulary_files, a name held by several files is a sync conflict.
+fn collections(conn: &Connection) -> Result<()> {
+    conn.execute("CREATE TABLE IF NOT EXISTS collections ( filename TEXT PRIMARY KEY, name TEXT NOT NULL, timestamp INT NOT NULL);", ())?;
+    conn.execute("CREATE INDEX IF NOT EXISTS collections_name_index ON collections(name COLLATE NOCASE);", ())?;
+    conn.execute("CREATE TABLE IF NOT EXISTS collection_words ( filename TEXT NOT NULL, query TEXT NOT NULL, position INT NOT NULL, PRIMARY KEY (filename, query));", ())?;
+    conn.execute("CREATE INDEX IF NOT EXISTS collection_words_query_index ON collection_words(query);", ())?;
+
+    Ok(())
+}
+
+pub fn latest_version() -> usize {
+    MIGRATIONS.len()
+}
+
+pub fn user_version(conn: &Connection) -> Result<usize> {
+    let version: i64 = conn.query_row("PRAGMA user_version;", (), |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+// Runs the migrations the database hasn't seen yet, each in its own transaction so a
+// failure leaves it at the last version that applied cleanly. A version newer than
+// this build knows about, e.g. after going back to an older release, is an error.
+pub fn migrate(conn: &Connection) -> Result<()> {
+    let version = user_version(conn)?;
+
+    if version > latest_version() {
+        return Err(anyhow!(
+            "cache.db is at schema version {}, this build only knows up to {}",
+            version,
+            latest_version()
+        ));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.unchecked_transaction()?;
+        migration(&tx)?;
+        tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+        tx.commit()?;
+        eprintln!("Migrated cache.db to schema version {}", index + 1);
+    }
+
+    Ok(())
+}
@@ -1,12 +1,21 @@
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
 use reqwest;
 use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
 use std::result::Result::Ok;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::annotations::Annotations;
 use crate::entry::{self};
+use crate::provider::{Completion, DictionaryProvider, HttpStatusError};
+use crate::schema::ResponseSchema;
 use crate::state;
 
+pub const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+pub const OPENAI_DEFAULT_MODEL: &str = "gpt-3.5-turbo";
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Root {
@@ -50,11 +59,56 @@ pub struct Message {
 pub struct ChatGPTQuery {
     model: String,
     messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    format_type: String,
+    json_schema: JsonSchemaFormat,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    name: String,
+    strict: bool,
+    schema: serde_json::Value,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamChunk {
+    #[serde(default)]
+    pub choices: Vec<StreamChoice>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamChoice {
+    #[serde(default)]
+    pub delta: Delta,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delta {
+    pub content: Option<String>,
 }
 
 fn sample_chinese_query() -> String {
     let e = entry::Entry {
         query: "sentence".to_string(),
+        source_lang: None,
+        annotations: Annotations::default(),
         meanings: vec![entry::Meaning {
             pos: "noun".to_string(),
             meanings: vec![entry::Item{ meaning: vec![
@@ -97,6 +151,8 @@ fn sample_chinese_query() -> String {
 fn sample_spanish_query() -> String {
     let e = entry::Entry {
         query: "sentence".to_string(),
+        source_lang: None,
+        annotations: Annotations::default(),
         meanings: vec![entry::Meaning {
             pos: "noun".to_string(),
             meanings: vec![entry::Item{ meaning: vec![
@@ -139,6 +195,8 @@ fn sample_spanish_query() -> String {
 fn sample_japanese_query() -> String {
     let e = entry::Entry {
         query: "sentence".to_string(),
+        source_lang: None,
+        annotations: Annotations::default(),
         meanings: vec![entry::Meaning {
             pos: "noun".to_string(),
             meanings: vec![entry::Item{ meaning: vec![
@@ -181,6 +239,8 @@ fn sample_japanese_query() -> String {
 fn sample_korean_query() -> String {
     let e = entry::Entry {
         query: "sentence".to_string(),
+        source_lang: None,
+        annotations: Annotations::default(),
         meanings: vec![entry::Meaning {
             pos: "noun".to_string(),
             meanings: vec![entry::Item{ meaning: vec![
@@ -223,6 +283,8 @@ fn sample_korean_query() -> String {
 fn sample_german_query() -> String {
     let e = entry::Entry {
         query: "sentence".to_string(),
+        source_lang: None,
+        annotations: Annotations::default(),
         meanings: vec![entry::Meaning {
             pos: "noun".to_string(),
             meanings: vec![entry::Item{ meaning: vec![
@@ -265,6 +327,8 @@ fn sample_german_query() -> String {
 fn sample_french_query() -> String {
     let e = entry::Entry {
         query: "sentence".to_string(),
+        source_lang: None,
+        annotations: Annotations::default(),
         meanings: vec![entry::Meaning {
             pos: "noun".to_string(),
             meanings: vec![entry::Item{ meaning: vec![
@@ -307,6 +371,8 @@ fn sample_french_query() -> String {
 fn sample_portuguese_query() -> String {
     let e = entry::Entry {
         query: "sentence".to_string(),
+        source_lang: None,
+        annotations: Annotations::default(),
         meanings: vec![entry::Meaning {
             pos: "noun".to_string(),
             meanings: vec![entry::Item{ meaning: vec![
@@ -346,7 +412,7 @@ fn sample_portuguese_query() -> String {
     res
 }
 
-fn assemble_query(query: &str, target_lang: &state::TargetLang) -> String {
+pub fn assemble_messages(query: &str, target_lang: &state::TargetLang) -> Vec<Message> {
     let language_str = match target_lang {
         state::TargetLang::Chinese => "Chinese",
         state::TargetLang::Spanish => "Spanish",
@@ -357,9 +423,7 @@ fn assemble_query(query: &str, target_lang: &state::TargetLang) -> String {
         state::TargetLang::Portuguese => "Portuguese",
     };
 
-    let query = ChatGPTQuery {
-        model: "gpt-3.5-turbo".to_string(),
-        messages: vec![Message {
+    vec![Message {
                 role: "system".to_string(),
                 content: format!("You are a dictionary bot. Given a query, reply its meaning and sample sentences in English and {} in JSON format.", language_str).to_string()
         },
@@ -382,83 +446,113 @@ fn assemble_query(query: &str, target_lang: &state::TargetLang) -> String {
         Message     {
             role: "user".to_string(),
             content: query.to_string()
-          }],
-    };
-    let res = serde_json::to_string(&query).unwrap();
+          }]
+}
 
-    res
+pub fn language_name(lang: &state::TargetLang) -> &'static str {
+    match lang {
+        state::TargetLang::Chinese => "Chinese",
+        state::TargetLang::Spanish => "Spanish",
+        state::TargetLang::Japanese => "Japanese",
+        state::TargetLang::Korean => "Korean",
+        state::TargetLang::German => "German",
+        state::TargetLang::French => "French",
+        state::TargetLang::Portuguese => "Portuguese",
+    }
 }
 
-pub async fn search(
-    query: &str,
-    auth_token: &str,
-    target_lang: &state::TargetLang,
-) -> Result<(i64,i64,entry::Entry)> {
-    let bearer_auth = format!("Bearer {}", auth_token);
-
-    let data = assemble_query(query, target_lang);
-
-    println!("{}", data);
-
-    let url = "https://api.openai.com/v1/chat/completions".to_string();
-    let client = reqwest::Client::new();
-    let response = client
-        .post(url)
-        .header(ACCEPT, "*/*")
-        .header(AUTHORIZATION, &bearer_auth)
-        .header(CONTENT_TYPE, "application/json")
-        .body(data)
-        .send()
-        .await
-        .unwrap();
-    match response.status() {
-        reqwest::StatusCode::OK => {
-            match response.json::<Root>().await {
-                Ok(parsed) => {
-                    println!("🔥 Success!");
-                    println!("💬 Response: {}", parsed.choices[0].message.content);
-
-                    match serde_json::from_str(&parsed.choices[0].message.content) {
-                        Ok(meanings) => {
-                            println!("{:?}", meanings);
-                            return Ok((parsed.usage.prompt_tokens, parsed.usage.completion_tokens, entry::Entry {
-                                query: query.to_string(),
-                                meanings: meanings,
-                            }));
-                        }
-                        Err(message) => {
-                            return Err(anyhow!(format!(
-                                "{} : {}",
-                                message.to_string(),
-                                parsed.choices[0].message.content
-                            )));
-                        }
-                    }
-                }
-                Err(_) => {
-                    println!("🛑 Hm, the response didn't match the shape we expected.");
-                    return Err(anyhow!(
-                        "🛑 Hm, the response didn't match the shape we expected."
-                    ));
-                }
-            };
-        }
-        reqwest::StatusCode::UNAUTHORIZED => {
-            println!("🛑 Status: UNAUTHORIZED - Need to grab a new token");
-            return Err(anyhow!("Status: UNAUTHORIZED - Need to grab a new token"));
-        }
-        reqwest::StatusCode::TOO_MANY_REQUESTS => {
-            println!("🛑 Status: 429 - Too many requests");
-            return Err(anyhow!("Status: 429 - Too many requests, this may happend if your API token was generated not too long ago. Please try again later."));
-        }
-        other => {
-            return Err(anyhow!(format!(
-                "🛑 Uh oh! Something unexpected happened: [{:#?} {:?}]",
-                other,
-                response.text().await
-            )));
-        }
-    };
+pub fn lang_text(lang: &state::TargetLang, text: &str) -> entry::Lang {
+    match lang {
+        state::TargetLang::Chinese => entry::Lang::Chinese(text.to_string()),
+        state::TargetLang::Spanish => entry::Lang::Spanish(text.to_string()),
+        state::TargetLang::Japanese => entry::Lang::Japanese(text.to_string()),
+        state::TargetLang::Korean => entry::Lang::Korean(text.to_string()),
+        state::TargetLang::German => entry::Lang::German(text.to_string()),
+        state::TargetLang::French => entry::Lang::French(text.to_string()),
+        state::TargetLang::Portuguese => entry::Lang::Portuguese(text.to_string()),
+    }
+}
+
+// (word for "court", its definition, an example sentence) in each language
+fn reverse_sample(source_lang: &state::TargetLang) -> (&'static str, &'static str, &'static str) {
+    match source_lang {
+        state::TargetLang::Chinese => ("法院", "法院：审理和判决法律案件的地方。", "这个案件被提交到了法院。"),
+        state::TargetLang::Spanish => ("tribunal", "tribunal: el lugar donde se juzgan y deciden los casos legales.", "El caso fue llevado al tribunal."),
+        state::TargetLang::Japanese => ("裁判所", "裁判所：法的な事件を審理し、判決を下す場所。", "その事件は裁判所に持ち込まれた。"),
+        state::TargetLang::Korean => ("법원", "법원: 법적 사건을 심리하고 판결하는 곳.", "그 사건은 법원으로 넘어갔다."),
+        state::TargetLang::German => ("Gericht", "Gericht: der Ort, an dem Rechtsfälle verhandelt und entschieden werden.", "Der Fall kam vor Gericht."),
+        state::TargetLang::French => ("tribunal", "tribunal : le lieu où les affaires judiciaires sont entendues et jugées.", "L'affaire a été portée devant le tribunal."),
+        state::TargetLang::Portuguese => ("tribunal", "tribunal: o lugar onde os casos judiciais são julgados e decididos.", "O caso foi levado ao tribunal."),
+    }
+}
+
+// Reverse lookup: the query is in `source_lang` and the answer lists English candidates,
+// each English meaning starting with the candidate word followed by its definition.
+pub fn assemble_reverse_messages(query: &str, source_lang: &state::TargetLang) -> Vec<Message> {
+    let language_str = language_name(source_lang);
+    let (sample_query, sample_meaning, sample_example) = reverse_sample(source_lang);
+
+    let sample = vec![entry::Meaning {
+        pos: "noun".to_string(),
+        meanings: vec![entry::Item {
+            meaning: vec![
+                entry::Lang::English("court: the place where legal cases are heard and decided.".to_string()),
+                lang_text(source_lang, sample_meaning),
+            ],
+            examples: vec![vec![
+                entry::Lang::English("The case was taken to court.".to_string()),
+                lang_text(source_lang, sample_example),
+            ]],
+        }],
+    }];
+
+    vec![
+        Message {
+            role: "system".to_string(),
+            content: format!("You are a dictionary bot. Given a query in {}, reply the English words or phrases it translates to. For each English candidate, start its English meaning with the candidate, then explain it, and give its meaning and sample sentences in English and {} in JSON format.", language_str, language_str),
+        },
+        Message {
+            role: "user".to_string(),
+            content: sample_query.to_string(),
+        },
+        Message {
+            role: "assistant".to_string(),
+            content: serde_json::to_string(&sample).unwrap(),
+        },
+        Message {
+            role: "user".to_string(),
+            content: query.to_string(),
+        },
+    ]
+}
+
+// Translates a batch of English texts, the reply is a JSON array in the same order.
+pub fn assemble_translation_messages(texts: &[String], target_lang: &state::TargetLang) -> Vec<Message> {
+    let language_str = language_name(target_lang);
+    let (_, sample_meaning, sample_example) = reverse_sample(target_lang);
+
+    vec![
+        Message {
+            role: "system".to_string(),
+            content: format!("You are a translator. Translate each English text in the JSON array into {}. Reply with a JSON array of the translations in the same order.", language_str),
+        },
+        Message {
+            role: "user".to_string(),
+            content: serde_json::to_string(&vec![
+                "court: the place where legal cases are heard and decided.",
+                "The case was taken to court.",
+            ])
+            .unwrap(),
+        },
+        Message {
+            role: "assistant".to_string(),
+            content: serde_json::to_string(&vec![sample_meaning, sample_example]).unwrap(),
+        },
+        Message {
+            role: "user".to_string(),
+            content: serde_json::to_string(texts).unwrap(),
+        },
+    ]
 }
 
 pub struct SentenceExampleQuery {
@@ -466,10 +560,10 @@ pub struct SentenceExampleQuery {
     pub meaning: String
 }
 
-fn assemble_sentence_example_query(
+pub fn assemble_sentence_example_messages(
     sentence_query: &SentenceExampleQuery,
     target_lang: &state::TargetLang,
-) -> String {
+) -> Vec<Message> {
     let language_str = match target_lang {
         state::TargetLang::Chinese => "Chinese",
         state::TargetLang::Spanish => "Spanish",
@@ -595,9 +689,7 @@ fn assemble_sentence_example_query(
         }
     };
 
-    let query = ChatGPTQuery {
-        model: "gpt-3.5-turbo".to_string(),
-        messages: vec![Message {
+    vec![Message {
                 role: "system".to_string(),
                 content: format!("You are a dictionary bot. Given a query, reply more sample sentences in English and {} in JSON format.", language_str).to_string()
         },
@@ -612,78 +704,261 @@ fn assemble_sentence_example_query(
         Message     {
             role: "user".to_string(),
             content: format!("Query: \"{}\" Meaning: \"{}\"]", sentence_query.query, sentence_query.meaning)
-          }],
-    };
-    let res = serde_json::to_string(&query).unwrap();
+          }]
+}
 
-    res
+pub struct OpenAIProvider {
+    base_url: String,
+    api_key: String,
+    model: String,
+    parse_attempts: usize,
+    // cleared once the server rejects a response_format, e.g. older models or
+    // compatible servers without structured outputs
+    structured_outputs: AtomicBool,
 }
 
-pub async fn search_example_sentences(
-    search_query: &SentenceExampleQuery,
-    auth_token: &str,
-    target_lang: &state::TargetLang,
-) -> Result<(i64, i64, Vec<Vec<entry::Lang>>)> {
-    let bearer_auth = format!("Bearer {}", auth_token);
-
-    let data = assemble_sentence_example_query(search_query, target_lang);
-
-    println!("{}", data);
-
-    let url = "https://api.openai.com/v1/chat/completions".to_string();
-    let client = reqwest::Client::new();
-    let response = client
-        .post(url)
-        .header(ACCEPT, "*/*")
-        .header(AUTHORIZATION, &bearer_auth)
-        .header(CONTENT_TYPE, "application/json")
-        .body(data)
-        .send()
-        .await
-        .unwrap();
-    match response.status() {
-        reqwest::StatusCode::OK => {
-            match response.json::<Root>().await {
-                Ok(parsed) => {
-                    println!("🔥 Success!");
-                    println!("💬 Response: {}", parsed.choices[0].message.content);
-                    match serde_json::from_str(&parsed.choices[0].message.content) {
-                        Ok(meanings) => {
-                            println!("{:?}", meanings);
-                            let result: Vec<Vec<entry::Lang>> = meanings;
-                            return Ok((parsed.usage.prompt_tokens, parsed.usage.completion_tokens, result));
-                        }
-                        Err(message) => {
-                            return Err(anyhow!(format!(
-                                "{} : {}",
-                                message.to_string(),
-                                parsed.choices[0].message.content
-                            )));
-                        }
-                    }
+impl OpenAIProvider {
+    pub fn new(base_url: &str, api_key: &str, model: &str, parse_attempts: usize) -> Self {
+        OpenAIProvider {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            parse_attempts,
+            structured_outputs: AtomicBool::new(true),
+        }
+    }
+
+    fn response_format(&self, schema: Option<&ResponseSchema>) -> Option<ResponseFormat> {
+        if !self.structured_outputs.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        schema.map(|schema| ResponseFormat {
+            format_type: "json_schema".to_string(),
+            json_schema: JsonSchemaFormat {
+                name: schema.name.clone(),
+                strict: true,
+                schema: schema.schema.clone(),
+            },
+        })
+    }
+
+    // Sends the query, and if the server turns down its response_format sends it again
+    // without one. The few-shot prompt is always part of the messages, so the reply
+    // still comes in the expected shape.
+    async fn send_with_fallback(&self, mut query: ChatGPTQuery) -> Result<reqwest::Response> {
+        match self.send(&query).await {
+            Err(error) if query.response_format.is_some() && is_rejected_request(&error) => {
+                eprintln!(
+                    "🛑 {} doesn't take a JSON schema, falling back to the few-shot prompt: {}",
+                    self.model, error
+                );
+                self.structured_outputs.store(false, Ordering::Relaxed);
+                query.response_format = None;
+                self.send(&query).await
+            }
+            result => result,
+        }
+    }
+
+    async fn send(&self, query: &ChatGPTQuery) -> Result<reqwest::Response> {
+        let data = serde_json::to_string(query)?;
+
+        eprintln!("{}", data);
+
+        let url = format!("{}/chat/completions", self.base_url);
+        let client = reqwest::Client::new();
+        let mut request = client
+            .post(url)
+            .header(ACCEPT, "*/*")
+            .header(CONTENT_TYPE, "application/json");
+
+        // local OpenAI compatible servers usually don't require a token
+        if !self.api_key.is_empty() {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", self.api_key));
+        }
+
+        let response = request.body(data).send().await?;
+        match response.status() {
+            reqwest::StatusCode::OK => Ok(response),
+            reqwest::StatusCode::UNAUTHORIZED => {
+                eprintln!("🛑 Status: UNAUTHORIZED - Need to grab a new token");
+                Err(HttpStatusError {
+                    status: 401,
+                    message: String::from("Status: UNAUTHORIZED - Need to grab a new token"),
                 }
-                Err(_) => {
-                    println!("🛑 Hm, the response didn't match the shape we expected.");
-                    return Err(anyhow!(
-                        "🛑 Hm, the response didn't match the shape we expected."
-                    ));
+                .into())
+            }
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                eprintln!("🛑 Status: 429 - Too many requests");
+                Err(HttpStatusError {
+                    status: 429,
+                    message: String::from("Status: 429 - Too many requests, this may happend if your API token was generated not too long ago. Please try again later."),
                 }
-            };
+                .into())
+            }
+            other => Err(HttpStatusError {
+                status: other.as_u16(),
+                message: format!(
+                    "🛑 Uh oh! Something unexpected happened: [{:#?} {:?}]",
+                    other,
+                    response.text().await
+                ),
+            }
+            .into()),
         }
-        reqwest::StatusCode::UNAUTHORIZED => {
-            println!("🛑 Status: UNAUTHORIZED - Need to grab a new token");
-            return Err(anyhow!("Status: UNAUTHORIZED - Need to grab a new token"));
+    }
+}
+
+// Only a complaint about the schema itself turns structured outputs off. Other bad
+// requests (prompt too long, unknown model, content filter) are 400s as well.
+fn is_rejected_request(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<HttpStatusError>() {
+        Some(HttpStatusError { status: 400 | 422, message }) => {
+            message.contains("response_format") || message.contains("json_schema")
         }
-        reqwest::StatusCode::TOO_MANY_REQUESTS => {
-            println!("🛑 Status: 429 - Too many requests");
-            return Err(anyhow!("Status: 429 - Too many requests, this may happend if your API token was generated not too long ago. Please try again later."));
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl DictionaryProvider for OpenAIProvider {
+    fn parse_attempts(&self) -> usize {
+        self.parse_attempts
+    }
+
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        schema: Option<&ResponseSchema>,
+    ) -> Result<Completion> {
+        let response = self
+            .send_with_fallback(ChatGPTQuery {
+                model: self.model.clone(),
+                messages,
+                response_format: self.response_format(schema),
+                ..Default::default()
+            })
+            .await?;
+
+        match response.json::<Root>().await {
+            Ok(parsed) => {
+                eprintln!("🔥 Success!");
+                if parsed.choices.is_empty() {
+                    return Err(anyhow!("🛑 The response contains no choices."));
+                }
+                eprintln!("💬 Response: {}", parsed.choices[0].message.content);
+                Ok(Completion {
+                    content: parsed.choices[0].message.content.clone(),
+                    prompt_tokens: parsed.usage.prompt_tokens,
+                    completion_tokens: parsed.usage.completion_tokens,
+                })
+            }
+            Err(_) => {
+                eprintln!("🛑 Hm, the response didn't match the shape we expected.");
+                Err(anyhow!(
+                    "🛑 Hm, the response didn't match the shape we expected."
+                ))
+            }
         }
-        other => {
-            return Err(anyhow!(format!(
-                "🛑 Uh oh! Something unexpected happened: [{:#?} {:?}]",
-                other,
-                response.text().await
-            )));
+    }
+
+    async fn complete_stream(
+        &self,
+        messages: Vec<Message>,
+        schema: Option<&ResponseSchema>,
+        on_delta: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Completion> {
+        let response = self
+            .send_with_fallback(ChatGPTQuery {
+                model: self.model.clone(),
+                messages,
+                stream: Some(true),
+                stream_options: Some(StreamOptions {
+                    include_usage: true,
+                }),
+                response_format: self.response_format(schema),
+            })
+            .await?;
+
+        let mut completion = Completion {
+            content: String::new(),
+            prompt_tokens: 0,
+            completion_tokens: 0,
+        };
+
+        // server-sent events, one `data: {chunk}` per line, terminated by `data: [DONE]`
+        let mut pending = Vec::<u8>::new();
+        let mut body = response.bytes_stream();
+
+        'events: while let Some(bytes) = body.next().await {
+            pending.extend_from_slice(&bytes?);
+
+            while let Some(newline) = pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=newline).collect();
+                let line = String::from_utf8_lossy(&line);
+                let data = match line.trim().strip_prefix("data:") {
+                    Some(data) => data.trim(),
+                    None => continue,
+                };
+
+                if data == "[DONE]" {
+                    break 'events;
+                }
+
+                let chunk: StreamChunk = match serde_json::from_str(data) {
+                    Ok(chunk) => chunk,
+                    Err(message) => {
+                        eprintln!("🛑 Unexpected stream chunk {}: {}", message, data);
+                        continue;
+                    }
+                };
+
+                for choice in chunk.choices {
+                    if let Some(content) = choice.delta.content {
+                        completion.content.push_str(&content);
+                        on_delta(&content);
+                    }
+                }
+
+                if let Some(usage) = chunk.usage {
+                    completion.prompt_tokens = usage.prompt_tokens;
+                    completion.completion_tokens = usage.completion_tokens;
+                }
+            }
         }
-    };
+
+        eprintln!("💬 Response: {}", completion.content);
+
+        Ok(completion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayProvider;
+
+    // the error the recorded request got back
+    fn replayed_error(request: &str) -> anyhow::Error {
+        let fixtures_path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let messages = vec![Message {
+            role: "user".to_string(),
+            content: request.to_string(),
+        }];
+        match futures::executor::block_on(ReplayProvider::new(fixtures_path).complete(messages, None)) {
+            Ok(_) => panic!("{} should have been turned down", request),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn a_rejected_schema_falls_back() {
+        assert!(is_rejected_request(&replayed_error("rejected response_format")));
+    }
+
+    #[test]
+    fn other_bad_requests_keep_the_schema() {
+        assert!(!is_rejected_request(&replayed_error("context length exceeded")));
+    }
 }
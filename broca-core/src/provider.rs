@@ -0,0 +1,309 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+
+use crate::annotations::Annotations;
+use crate::anthropic::AnthropicProvider;
+use crate::entry::{self};
+use crate::openai::{self, Message, OpenAIProvider, SentenceExampleQuery};
+use crate::repair;
+use crate::replay::ReplayProvider;
+use crate::schema::{self, ResponseSchema};
+use crate::state::{ProviderConfig, TargetLang};
+use crate::stream::{EntryStreamParser, Fragment, SearchEvent};
+
+// A provider answered with a non-success HTTP status. Kept typed so callers
+// can tell transient failures (rate limits, server errors) from permanent ones.
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+// Whether retrying the same request later might succeed.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    if let Some(error) = error.downcast_ref::<HttpStatusError>() {
+        return error.status == 429 || error.status >= 500;
+    }
+    if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+        return error.is_timeout() || error.is_connect();
+    }
+    false
+}
+
+pub const DEFAULT_PARSE_ATTEMPTS: usize = 3;
+
+pub struct Completion {
+    pub content: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+}
+
+// A chat model that can serve dictionary lookups. Implementations only need to
+// know how to send a list of chat messages; prompts and response parsing are shared.
+// `schema` describes the expected reply for providers that can enforce it, the
+// others ignore it and rely on the few-shot examples in the messages.
+#[async_trait]
+pub trait DictionaryProvider: Send + Sync {
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        schema: Option<&ResponseSchema>,
+    ) -> Result<Completion>;
+
+    // How many replies a lookup may take before giving up on malformed output.
+    fn parse_attempts(&self) -> usize {
+        DEFAULT_PARSE_ATTEMPTS
+    }
+
+    // Providers without streaming support deliver the whole reply as a single delta.
+    async fn complete_stream(
+        &self,
+        messages: Vec<Message>,
+        schema: Option<&ResponseSchema>,
+        on_delta: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<Completion> {
+        let completion = self.complete(messages, schema).await?;
+        on_delta(&completion.content);
+        Ok(completion)
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        target_lang: &TargetLang,
+        source_lang: Option<&TargetLang>,
+    ) -> Result<(i64, i64, entry::Entry)> {
+        let messages = search_messages(query, target_lang, source_lang);
+        let schema = schema::meanings_schema(source_lang.unwrap_or(target_lang));
+        let completion = self.complete(messages.clone(), Some(&schema)).await?;
+
+        let (prompt_tokens, completion_tokens, meanings) =
+            parse_with_retries(self, messages, Some(&schema), completion, parse_meanings)
+                .await?;
+        eprintln!("{:?}", meanings);
+
+        Ok((
+            prompt_tokens,
+            completion_tokens,
+            entry::Entry {
+                query: query.to_string(),
+                meanings,
+                source_lang: source_lang.cloned(),
+                annotations: Annotations::default(),
+            },
+        ))
+    }
+
+    async fn search_streaming(
+        &self,
+        query: &str,
+        target_lang: &TargetLang,
+        source_lang: Option<&TargetLang>,
+        on_event: &(dyn Fn(SearchEvent) + Send + Sync),
+    ) -> Result<(i64, i64, entry::Entry)> {
+        let parser = Mutex::new(EntryStreamParser::new());
+        let on_delta = |delta: &str| {
+            let fragments = parser.lock().unwrap().push(delta);
+            for fragment in fragments {
+                on_event(match fragment {
+                    Fragment::Item {
+                        meaning_index,
+                        item_index,
+                        pos,
+                        item,
+                    } => SearchEvent::Item {
+                        query: query.to_string(),
+                        meaning_index,
+                        item_index,
+                        pos,
+                        item,
+                    },
+                    Fragment::Meaning { index, meaning } => SearchEvent::Meaning {
+                        query: query.to_string(),
+                        index,
+                        meaning,
+                    },
+                });
+            }
+        };
+
+        let messages = search_messages(query, target_lang, source_lang);
+        let schema = schema::meanings_schema(source_lang.unwrap_or(target_lang));
+        let completion = self
+            .complete_stream(messages.clone(), Some(&schema), &on_delta)
+            .await?;
+
+        // retries aren't streamed, the Finished event carries whatever the retry produced
+        let (prompt_tokens, completion_tokens, meanings) =
+            parse_with_retries(self, messages, Some(&schema), completion, parse_meanings)
+                .await?;
+
+        Ok((
+            prompt_tokens,
+            completion_tokens,
+            entry::Entry {
+                query: query.to_string(),
+                meanings,
+                source_lang: source_lang.cloned(),
+                annotations: Annotations::default(),
+            },
+        ))
+    }
+
+    async fn translate(
+        &self,
+        texts: Vec<String>,
+        target_lang: &TargetLang,
+    ) -> Result<(i64, i64, Vec<String>)> {
+        let messages = openai::assemble_translation_messages(&texts, target_lang);
+        let completion = self.complete(messages.clone(), None).await?;
+
+        let expected = texts.len();
+        parse_with_retries(self, messages, None, completion, |content: &str| {
+            let translations = repair::parse_lenient::<Vec<String>>(content)?;
+            if translations.len() != expected {
+                return Err(format!(
+                    "expected {} translations, got {}",
+                    expected,
+                    translations.len()
+                ));
+            }
+            Ok(translations)
+        })
+        .await
+    }
+
+    async fn search_example_sentences(
+        &self,
+        search_query: &SentenceExampleQuery,
+        target_lang: &TargetLang,
+    ) -> Result<(i64, i64, Vec<Vec<entry::Lang>>)> {
+        let messages = openai::assemble_sentence_example_messages(search_query, target_lang);
+        let schema = schema::examples_schema(target_lang);
+        let completion = self.complete(messages.clone(), Some(&schema)).await?;
+
+        let (prompt_tokens, completion_tokens, examples) =
+            parse_with_retries(self, messages, Some(&schema), completion, |content: &str| {
+                let examples = repair::parse_lenient::<Vec<Vec<entry::Lang>>>(&schema::unwrap(
+                    content, "examples",
+                ))?;
+                repair::validate_examples(&examples)?;
+                Ok(examples)
+            })
+            .await?;
+        eprintln!("{:?}", examples);
+
+        Ok((prompt_tokens, completion_tokens, examples))
+    }
+}
+
+fn parse_meanings(content: &str) -> std::result::Result<entry::Meanings, String> {
+    let meanings =
+        repair::parse_lenient::<entry::Meanings>(&schema::unwrap(content, "meanings"))?;
+    repair::validate_meanings(&meanings)?;
+    Ok(meanings)
+}
+
+// Parses a reply, and while that fails shows the model its reply together with the error
+// and asks again, until the provider's parse_attempts are used up. Token counts add up
+// over all attempts.
+async fn parse_with_retries<P, T, F>(
+    provider: &P,
+    mut messages: Vec<Message>,
+    schema: Option<&ResponseSchema>,
+    mut completion: Completion,
+    parse: F,
+) -> Result<(i64, i64, T)>
+where
+    P: DictionaryProvider + ?Sized,
+    F: Fn(&str) -> std::result::Result<T, String>,
+{
+    let mut prompt_tokens = completion.prompt_tokens;
+    let mut completion_tokens = completion.completion_tokens;
+    let mut attempt = 1;
+
+    loop {
+        let error = match parse(&completion.content) {
+            Ok(value) => return Ok((prompt_tokens, completion_tokens, value)),
+            Err(error) => error,
+        };
+
+        if attempt >= provider.parse_attempts() {
+            return Err(anyhow!(format!("{} : {}", error, completion.content)));
+        }
+        eprintln!("Unusable reply on attempt {}, asking again: {}", attempt, error);
+
+        messages.push(Message {
+            role: "assistant".to_string(),
+            content: completion.content.clone(),
+        });
+        messages.push(Message {
+            role: "user".to_string(),
+            content: format!(
+                "Your reply could not be used: {}. Reply again with only the corrected JSON, in the same format.",
+                error
+            ),
+        });
+
+        completion = provider.complete(messages.clone(), schema).await?;
+        prompt_tokens += completion.prompt_tokens;
+        completion_tokens += completion.completion_tokens;
+        attempt += 1;
+    }
+}
+
+fn search_messages(
+    query: &str,
+    target_lang: &TargetLang,
+    source_lang: Option<&TargetLang>,
+) -> Vec<Message> {
+    match source_lang {
+        Some(source_lang) => openai::assemble_reverse_messages(query, source_lang),
+        None => openai::assemble_messages(query, target_lang),
+    }
+}
+
+pub fn from_config(
+    config: &ProviderConfig,
+    openai_token: &str,
+    parse_attempts: usize,
+) -> Arc<dyn DictionaryProvider> {
+    match config {
+        ProviderConfig::OpenAI => Arc::new(OpenAIProvider::new(
+            openai::OPENAI_BASE_URL,
+            openai_token,
+            openai::OPENAI_DEFAULT_MODEL,
+            parse_attempts,
+        )),
+        ProviderConfig::OpenAICompatible {
+            base_url,
+            model,
+            api_key,
+        } => Arc::new(OpenAIProvider::new(
+            base_url,
+            api_key.as_deref().unwrap_or(""),
+            model,
+            parse_attempts,
+        )),
+        ProviderConfig::Anthropic {
+            base_url,
+            model,
+            api_key,
+        } => Arc::new(AnthropicProvider::new(
+            base_url.as_deref().unwrap_or(crate::anthropic::ANTHROPIC_BASE_URL),
+            api_key,
+            model,
+            parse_attempts,
+        )),
+        ProviderConfig::Replay { fixtures_path } => Arc::new(ReplayProvider::new(fixtures_path)),
+    }
+}
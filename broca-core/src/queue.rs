@@ -0,0 +1,343 @@
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Longest wait between two attempts of the same word.
+const MAX_BACKOFF_MILLIS: u64 = 5 * 60 * 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct QueueConfig {
+    // lookups running at the same time
+    pub workers: usize,
+    // sustained request rate, bursts of up to `workers` requests are allowed
+    pub requests_per_minute: f64,
+    pub max_attempts: i64,
+    // first retry waits this long, doubling with every further attempt
+    pub backoff_millis: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            workers: 3,
+            requests_per_minute: 20.0,
+            max_attempts: 5,
+            backoff_millis: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueueEvent {
+    Started {
+        query: String,
+        attempt: i64,
+    },
+    Retrying {
+        query: String,
+        attempt: i64,
+        delay_millis: u64,
+        error: String,
+    },
+    Finished {
+        query: String,
+        entry: String,
+    },
+    Failed {
+        query: String,
+        error: String,
+    },
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct QueueStatus {
+    pub pending: i64,
+    pub running: i64,
+    pub done: i64,
+    pub failed: i64,
+}
+
+pub struct Job {
+    pub query: String,
+    // attempts made before this one
+    pub attempts: i64,
+}
+
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+pub fn create_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS lookup_queue (
+            query TEXT UNIQUE,
+            status TEXT NOT NULL,
+            attempts INT NOT NULL,
+            next_attempt INT NOT NULL,
+            error TEXT,
+            created INT NOT NULL
+        );",
+        (),
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS lookup_queue_status_index ON lookup_queue (status, next_attempt);",
+        (),
+    )?;
+    Ok(())
+}
+
+// Queues words for lookup, returns how many were added. Words already waiting are left
+// alone, finished or failed ones are queued again.
+pub fn enqueue(conn: &Connection, queries: &[String]) -> Result<usize> {
+    let now = now_millis();
+    let mut count = 0;
+
+    for query in queries {
+        count += conn.execute(
+            "INSERT INTO lookup_queue (query, status, attempts, next_attempt, error, created) VALUES (?1, 'pending', 0, ?2, NULL, ?2)
+            ON CONFLICT(query) DO UPDATE SET status = 'pending', attempts = 0, next_attempt = ?2, error = NULL
+            WHERE status IN ('done', 'failed');",
+            (query, now),
+        )?;
+    }
+
+    Ok(count)
+}
+
+// Jobs left running by a worker that didn't finish, e.g. because the app quit.
+pub fn reset_running(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE lookup_queue SET status = 'pending' WHERE status = 'running';",
+        (),
+    )?;
+    Ok(())
+}
+
+// Takes the oldest job that is due, marking it running in the same statement so
+// concurrent workers never get the same word.
+pub fn claim(conn: &Connection) -> Result<Option<Job>> {
+    let job = conn
+        .query_row(
+            "UPDATE lookup_queue SET status = 'running'
+            WHERE rowid = (SELECT rowid FROM lookup_queue WHERE status = 'pending' AND next_attempt <= ?1 ORDER BY created LIMIT 1)
+            RETURNING query, attempts;",
+            [now_millis()],
+            |row| {
+                rusqlite::Result::Ok(Job {
+                    query: row.get(0)?,
+                    attempts: row.get(1)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(job)
+}
+
+// When the earliest job waiting for a retry becomes due, None if nothing is pending.
+pub fn next_due(conn: &Connection) -> Result<Option<i64>> {
+    let next: Option<i64> = conn.query_row(
+        "SELECT MIN(next_attempt) FROM lookup_queue WHERE status = 'pending';",
+        (),
+        |row| row.get(0),
+    )?;
+    Ok(next)
+}
+
+pub fn finish(conn: &Connection, query: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE lookup_queue SET status = 'done', attempts = attempts + 1, error = NULL WHERE query = ?1;",
+        [query],
+    )?;
+    Ok(())
+}
+
+pub fn retry(conn: &Connection, query: &str, delay_millis: u64, error: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE lookup_queue SET status = 'pending', attempts = attempts + 1, next_attempt = ?2, error = ?3 WHERE query = ?1;",
+        (query, now_millis() + delay_millis as i64, error),
+    )?;
+    Ok(())
+}
+
+pub fn fail(conn: &Connection, query: &str, error: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE lookup_queue SET status = 'failed', attempts = attempts + 1, error = ?2 WHERE query = ?1;",
+        (query, error),
+    )?;
+    Ok(())
+}
+
+pub fn status(conn: &Connection) -> Result<QueueStatus> {
+    let mut status = QueueStatus::default();
+
+    let mut stmt = conn.prepare_cached("SELECT status, COUNT(*) FROM lookup_queue GROUP BY status;")?;
+    let rows = stmt.query_map((), |row| {
+        rusqlite::Result::Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+    })?;
+
+    for row in rows {
+        let (name, count) = row?;
+        match name.as_str() {
+            "pending" => status.pending = count,
+            "running" => status.running = count,
+            "done" => status.done = count,
+            "failed" => status.failed = count,
+            _ => {}
+        }
+    }
+
+    Ok(status)
+}
+
+pub fn backoff(config: &QueueConfig, attempts: i64) -> u64 {
+    let factor = 2u64.saturating_pow(attempts.clamp(0, 32) as u32);
+    config.backoff_millis.saturating_mul(factor).min(MAX_BACKOFF_MILLIS)
+}
+
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    // tokens per second
+    rate: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f64, per_minute: f64) -> Self {
+        TokenBucket {
+            capacity,
+            tokens: capacity,
+            rate: per_minute / 60.0,
+            last: Instant::now(),
+        }
+    }
+
+    // Takes a token if there is one, otherwise says how long until the next one.
+    pub fn take(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * self.rate)
+            .min(self.capacity);
+        self.last = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return None;
+        }
+
+        if self.rate <= 0.0 {
+            return Some(Duration::from_secs(60));
+        }
+        Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_with(queries: &[&str]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+        let queries: Vec<String> = queries.iter().map(|q| q.to_string()).collect();
+        assert_eq!(enqueue(&conn, &queries).unwrap(), queries.len());
+        conn
+    }
+
+    fn next_attempt(conn: &Connection, query: &str) -> i64 {
+        conn.query_row(
+            "SELECT next_attempt FROM lookup_queue WHERE query = ?1;",
+            [query],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn a_claimed_job_is_not_handed_out_again() {
+        let conn = queue_with(&["serendipity"]);
+
+        let job = claim(&conn).unwrap().unwrap();
+        assert_eq!(job.query, "serendipity");
+        assert_eq!(job.attempts, 0);
+        assert!(claim(&conn).unwrap().is_none());
+        assert_eq!(status(&conn).unwrap().running, 1);
+
+        // e.g. the app quit halfway through the lookup
+        reset_running(&conn).unwrap();
+        let job = claim(&conn).unwrap().unwrap();
+        assert_eq!(job.query, "serendipity");
+    }
+
+    #[test]
+    fn a_retry_waits_and_counts_the_attempt() {
+        let conn = queue_with(&["serendipity"]);
+
+        let job = claim(&conn).unwrap().unwrap();
+        let before = now_millis();
+        retry(&conn, &job.query, 60_000, "timed out").unwrap();
+
+        assert!(next_attempt(&conn, "serendipity") >= before + 60_000);
+        assert_eq!(next_due(&conn).unwrap(), Some(next_attempt(&conn, "serendipity")));
+        assert!(claim(&conn).unwrap().is_none());
+
+        retry(&conn, "serendipity", 0, "timed out").unwrap();
+        let job = claim(&conn).unwrap().unwrap();
+        assert_eq!(job.attempts, 2);
+    }
+
+    #[test]
+    fn finished_and_failed_jobs_can_be_queued_again() {
+        let conn = queue_with(&["serendipity", "ephemeral"]);
+
+        let first = claim(&conn).unwrap().unwrap();
+        finish(&conn, &first.query).unwrap();
+        let second = claim(&conn).unwrap().unwrap();
+        fail(&conn, &second.query, "no such word").unwrap();
+
+        let status = status(&conn).unwrap();
+        assert_eq!((status.pending, status.done, status.failed), (0, 1, 1));
+        assert_eq!(next_due(&conn).unwrap(), None);
+
+        let queries = vec!["serendipity".to_string(), "ephemeral".to_string()];
+        assert_eq!(enqueue(&conn, &queries).unwrap(), 2);
+        // already waiting, left alone
+        assert_eq!(enqueue(&conn, &queries).unwrap(), 0);
+        assert_eq!(claim(&conn).unwrap().unwrap().attempts, 0);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let config = QueueConfig::default();
+
+        assert_eq!(backoff(&config, 0), 2000);
+        assert_eq!(backoff(&config, 1), 4000);
+        assert_eq!(backoff(&config, 2), 8000);
+        assert_eq!(backoff(&config, 10), MAX_BACKOFF_MILLIS);
+        assert_eq!(backoff(&config, i64::MAX), MAX_BACKOFF_MILLIS);
+    }
+
+    #[test]
+    fn token_bucket_allows_a_burst_then_waits() {
+        let mut bucket = TokenBucket::new(3.0, 60.0);
+
+        for _ in 0..3 {
+            assert_eq!(bucket.take(), None);
+        }
+        let wait = bucket.take().unwrap();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn token_bucket_without_a_rate_waits_a_minute() {
+        let mut bucket = TokenBucket::new(1.0, 0.0);
+
+        assert_eq!(bucket.take(), None);
+        assert_eq!(bucket.take(), Some(Duration::from_secs(60)));
+    }
+}
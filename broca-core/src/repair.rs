@@ -0,0 +1,277 @@
+use serde::de::DeserializeOwned;
+
+use crate::entry::{Lang, Meanings};
+
+// Cuts the JSON document out of a reply that may wrap it in a markdown code fence
+// or surround it with prose. A document that never closes runs to the end of the text.
+pub fn extract_json(content: &str) -> &str {
+    let mut text = content.trim();
+
+    if let Some(fence) = text.find("```") {
+        let after = &text[fence + 3..];
+        // skip the language tag, e.g. ```json
+        let after = match after.find('\n') {
+            Some(newline) => &after[newline + 1..],
+            None => after,
+        };
+        text = match after.find("```") {
+            Some(end) => &after[..end],
+            None => after,
+        };
+    }
+
+    let start = match text.find(|c| c == '[' || c == '{') {
+        Some(start) => start,
+        None => return text.trim(),
+    };
+
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, c) in text[start..].char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => depth += 1,
+            ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &text[start..=start + index];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    text[start..].trim_end()
+}
+
+fn next_significant(chars: &[char], from: usize) -> Option<char> {
+    chars[from..].iter().copied().find(|c| !c.is_whitespace())
+}
+
+// Fixes the mistakes models commonly make in otherwise sound JSON: trailing commas,
+// curly quotes around keys and values, raw line breaks inside strings, and output
+// cut off before the closing brackets.
+pub fn repair_json(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    let mut stack = Vec::<char>::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, &c) in chars.iter().enumerate() {
+        if in_string {
+            match c {
+                _ if escaped => {
+                    escaped = false;
+                    result.push(c);
+                }
+                '\\' => {
+                    escaped = true;
+                    result.push(c);
+                }
+                '"' => {
+                    in_string = false;
+                    result.push('"');
+                }
+                '\u{201c}' | '\u{201d}' if is_string_end(&chars, index + 1) => {
+                    in_string = false;
+                    result.push('"');
+                }
+                '\n' => result.push_str("\\n"),
+                '\t' => result.push_str("\\t"),
+                '\r' => {}
+                _ => result.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' | '\u{201c}' | '\u{201d}' => {
+                in_string = true;
+                result.push('"');
+            }
+            '[' => {
+                stack.push(']');
+                result.push(c);
+            }
+            '{' => {
+                stack.push('}');
+                result.push(c);
+            }
+            ']' | '}' => {
+                // a mismatched bracket is most likely meant to close the innermost one
+                if let Some(close) = stack.pop() {
+                    result.push(close);
+                }
+            }
+            ',' => {
+                if !matches!(next_significant(&chars, index + 1), Some(']') | Some('}') | None) {
+                    result.push(c);
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+
+    // the reply was cut off, close whatever is still open
+    if in_string {
+        if escaped {
+            result.pop();
+        }
+        result.push('"');
+    }
+    let trimmed_len = result.trim_end().trim_end_matches(',').len();
+    result.truncate(trimmed_len);
+    if result.ends_with(':') {
+        result.push_str("null");
+    }
+    while let Some(close) = stack.pop() {
+        result.push(close);
+    }
+
+    result
+}
+
+// A curly quote inside a string only ends it when JSON syntax follows.
+fn is_string_end(chars: &[char], from: usize) -> bool {
+    matches!(
+        next_significant(chars, from),
+        Some(',') | Some(':') | Some(']') | Some('}') | None
+    )
+}
+
+// Parses a model reply as T, first as is, then cut out of the surrounding text, then repaired.
+// The error is the one from the unrepaired document, which is what the model needs to see.
+pub fn parse_lenient<T: DeserializeOwned>(content: &str) -> Result<T, String> {
+    if let Ok(value) = serde_json::from_str(content) {
+        return Ok(value);
+    }
+
+    let extracted = extract_json(content);
+    let error = match serde_json::from_str(extracted) {
+        Ok(value) => return Ok(value),
+        Err(error) => error.to_string(),
+    };
+
+    match serde_json::from_str(&repair_json(extracted)) {
+        Ok(value) => {
+            eprintln!("Repaired malformed JSON: {}", error);
+            Ok(value)
+        }
+        Err(_) => Err(error),
+    }
+}
+
+fn has_english(langs: &[Lang]) -> bool {
+    langs
+        .iter()
+        .any(|l| matches!(l, Lang::English(s) if !s.trim().is_empty()))
+}
+
+// Checks what serde can't: that the lookup actually has content in the expected places.
+pub fn validate_meanings(meanings: &Meanings) -> Result<(), String> {
+    if meanings.is_empty() {
+        return Err(String::from("the list of meanings is empty"));
+    }
+
+    for (mi, meaning) in meanings.iter().enumerate() {
+        if meaning.pos.trim().is_empty() {
+            return Err(format!("meaning {} has an empty \"pos\"", mi));
+        }
+        if meaning.meanings.is_empty() {
+            return Err(format!("meaning {} has no entries in \"meanings\"", mi));
+        }
+        for (ii, item) in meaning.meanings.iter().enumerate() {
+            if !has_english(&item.meaning) {
+                return Err(format!(
+                    "item {} of meaning {} has no English definition",
+                    ii, mi
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn validate_examples(examples: &[Vec<Lang>]) -> Result<(), String> {
+    if examples.is_empty() {
+        return Err(String::from("the list of examples is empty"));
+    }
+
+    for (index, example) in examples.iter().enumerate() {
+        if !has_english(example) {
+            return Err(format!("example {} has no English sentence", index));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_parses(text: &str) {
+        if let Err(error) = serde_json::from_str::<serde_json::Value>(text) {
+            panic!("{:?} is still broken: {}", text, error);
+        }
+    }
+
+    #[test]
+    fn valid_json_passes_through_unchanged() {
+        let text = "[{\"pos\": \"noun\", \"meanings\": [\"a, b]\", {\"c\": null}]},\n {\"d\": [1, 2.5e3]}]";
+        assert_eq!(repair_json(text), text);
+    }
+
+    #[test]
+    fn escaped_quotes_stay_inside_the_string() {
+        let text = r#"{"example": "He said \"it was serendipity\", and left.", "pos": "noun"}"#;
+        assert_eq!(repair_json(text), text);
+
+        let cut = r#"{"example": "He said \"it was"#;
+        assert_eq!(repair_json(cut), r#"{"example": "He said \"it was"}"#);
+    }
+
+    #[test]
+    fn output_cut_off_inside_a_string_is_closed() {
+        let repaired = repair_json(r#"[{"pos": "noun", "meanings": ["The occurrence of"#);
+        assert_eq!(repaired, r#"[{"pos": "noun", "meanings": ["The occurrence of"]}]"#);
+
+        // halfway through an escape sequence
+        assert_eq!(repair_json(r#"["a \"#), r#"["a "]"#);
+    }
+
+    #[test]
+    fn output_cut_off_between_values_is_closed() {
+        assert_eq!(repair_json(r#"[{"pos": "noun"},"#), r#"[{"pos": "noun"}]"#);
+        assert_eq!(repair_json(r#"{"pos":"#), r#"{"pos":null}"#);
+    }
+
+    #[test]
+    fn trailing_commas_are_dropped() {
+        let repaired = repair_json("{\"meanings\": [\"one\", \"two\", ], \"pos\": \"noun\",\n}");
+        assert_eq!(repaired, "{\"meanings\": [\"one\", \"two\" ], \"pos\": \"noun\"\n}");
+        assert_parses(&repaired);
+    }
+
+    #[test]
+    fn curly_quotes_and_line_breaks_are_fixed() {
+        let repaired = repair_json("{\u{201c}pos\u{201d}: \u{201c}noun\u{201d}, \"example\": \"first\nsecond\"}");
+        assert_eq!(repaired, "{\"pos\": \"noun\", \"example\": \"first\\nsecond\"}");
+        assert_parses(&repaired);
+    }
+}
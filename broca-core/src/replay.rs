@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use glob::glob;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::openai::{Message, Root};
+use crate::provider::{Completion, DictionaryProvider, HttpStatusError};
+use crate::schema::ResponseSchema;
+
+// One recorded exchange. `request` is the last user message of the conversation,
+// `response` the chat completion body the API sent back for it, or `error` when the
+// API turned the request down.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Fixture {
+    pub request: String,
+    #[serde(default)]
+    pub response: Root,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RecordedError>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordedError {
+    pub status: u16,
+    // the response body as the API sent it
+    pub body: String,
+}
+
+// Answers from recorded chat completions instead of the network, for tests and
+// for working on the app offline. Every *.json file in `fixtures_path` holds one
+// Fixture; the files are read on each request so new recordings are picked up.
+pub struct ReplayProvider {
+    fixtures_path: PathBuf,
+    requests: Mutex<Vec<String>>,
+}
+
+impl ReplayProvider {
+    pub fn new(fixtures_path: &str) -> Self {
+        ReplayProvider {
+            fixtures_path: PathBuf::from(fixtures_path),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    // every request served so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn find(&self, request: &str) -> Result<Fixture> {
+        let pattern = self.fixtures_path.join("*.json");
+
+        for path in glob(pattern.to_str().unwrap())? {
+            let path = path?;
+            let fixture: Fixture = serde_json::from_slice(&std::fs::read(&path)?)
+                .map_err(|e| anyhow!("Bad fixture {}: {}", path.display(), e))?;
+            if fixture.request == request {
+                return Ok(fixture);
+            }
+        }
+
+        Err(anyhow!(
+            "No recorded response for {:?} in {}",
+            request,
+            self.fixtures_path.display()
+        ))
+    }
+}
+
+#[async_trait]
+impl DictionaryProvider for ReplayProvider {
+    async fn complete(
+        &self,
+        messages: Vec<Message>,
+        _schema: Option<&ResponseSchema>,
+    ) -> Result<Completion> {
+        let request = messages
+            .iter()
+            .rev()
+            .find(|m| m.role == "user")
+            .map(|m| m.content.clone())
+            .unwrap_or_default();
+
+        self.requests.lock().unwrap().push(request.clone());
+
+        let fixture = self.find(&request)?;
+        if let Some(error) = fixture.error {
+            return Err(HttpStatusError {
+                status: error.status,
+                message: format!("🛑 Status: {} - {}", error.status, error.body),
+            }
+            .into());
+        }
+
+        let choice = fixture
+            .response
+            .choices
+            .first()
+            .ok_or_else(|| anyhow!("🛑 The response contains no choices."))?;
+
+        Ok(Completion {
+            content: choice.message.content.clone(),
+            prompt_tokens: fixture.response.usage.prompt_tokens,
+            completion_tokens: fixture.response.usage.completion_tokens,
+        })
+    }
+}
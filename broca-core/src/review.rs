@@ -0,0 +1,120 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const MIN_EASE: f64 = 1.3;
+const INITIAL_EASE: f64 = 2.5;
+
+// SM-2 review state of a single word, stored in the review/ folder of the workspace
+// so the schedule syncs across devices together with the vocabulary.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReviewState {
+    pub query: String,
+    pub ease: f64,
+    // interval in days
+    pub interval: i64,
+    pub repetitions: i64,
+    // unix timestamp in seconds
+    pub due: u64,
+    pub lapses: i64,
+    pub last_reviewed: u64,
+}
+
+impl ReviewState {
+    pub fn new(query: &str, now: u64) -> Self {
+        ReviewState {
+            query: query.to_string(),
+            ease: INITIAL_EASE,
+            interval: 0,
+            repetitions: 0,
+            due: now,
+            lapses: 0,
+            last_reviewed: 0,
+        }
+    }
+
+    // grade follows SM-2: 0 (complete blackout) to 5 (perfect response).
+    // Anything below 3 counts as a failed recall.
+    pub fn grade(&mut self, grade: u8, now: u64) -> Result<()> {
+        if grade > 5 {
+            return Err(anyhow!("Grade must be between 0 and 5."));
+        }
+
+        let q = grade as f64;
+
+        if grade < 3 {
+            if self.repetitions > 0 {
+                self.lapses += 1;
+            }
+            self.repetitions = 0;
+            self.interval = 1;
+        } else {
+            self.repetitions += 1;
+            self.interval = match self.repetitions {
+                1 => 1,
+                2 => 6,
+                _ => (self.interval as f64 * self.ease).round() as i64,
+            };
+        }
+
+        self.ease = (self.ease + (0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02))).max(MIN_EASE);
+        self.due = now + self.interval as u64 * SECONDS_PER_DAY;
+        self.last_reviewed = now;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn intervals_go_one_six_then_times_ease() {
+        let mut review = ReviewState::new("serendipity", NOW);
+
+        review.grade(4, NOW).unwrap();
+        assert_eq!(review.interval, 1);
+        review.grade(4, NOW).unwrap();
+        assert_eq!(review.interval, 6);
+
+        let ease = review.ease;
+        review.grade(4, NOW).unwrap();
+        assert_eq!(review.interval, (6.0 * ease).round() as i64);
+        assert_eq!(review.due, NOW + review.interval as u64 * SECONDS_PER_DAY);
+    }
+
+    #[test]
+    fn a_lapse_starts_over_at_one_day() {
+        let mut review = ReviewState::new("serendipity", NOW);
+        for _ in 0..3 {
+            review.grade(5, NOW).unwrap();
+        }
+        assert!(review.interval > 6);
+
+        review.grade(1, NOW).unwrap();
+        assert_eq!(review.interval, 1);
+        assert_eq!(review.repetitions, 0);
+        assert_eq!(review.lapses, 1);
+    }
+
+    #[test]
+    fn ease_stays_above_the_minimum() {
+        let mut review = ReviewState::new("serendipity", NOW);
+        for _ in 0..20 {
+            review.grade(0, NOW).unwrap();
+            assert!(review.ease >= MIN_EASE);
+        }
+        assert_eq!(review.ease, MIN_EASE);
+    }
+
+    #[test]
+    fn grades_above_five_are_rejected() {
+        let mut review = ReviewState::new("serendipity", NOW);
+        assert!(review.grade(6, NOW).is_err());
+        assert_eq!(review.repetitions, 0);
+        assert_eq!(review.last_reviewed, 0);
+    }
+}
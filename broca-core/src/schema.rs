@@ -0,0 +1,104 @@
+use serde_json::{json, Value};
+
+use crate::openai::language_name;
+use crate::repair;
+use crate::state::TargetLang;
+
+// A JSON schema the reply has to follow, for providers that can enforce one.
+// Structured outputs need an object at the root, so lists are wrapped in one
+// and unwrap() takes them out again.
+#[derive(Debug, Clone)]
+pub struct ResponseSchema {
+    pub name: String,
+    pub schema: Value,
+}
+
+// These mirror the serde shapes in entry.rs and need to change with them.
+// `Lang` is an externally tagged enum, i.e. an object with a single language key.
+fn lang_schema(langs: &[&str]) -> Value {
+    let variants: Vec<Value> = langs
+        .iter()
+        .map(|lang| {
+            json!({
+                "type": "object",
+                "properties": { *lang: { "type": "string" } },
+                "required": [lang],
+                "additionalProperties": false
+            })
+        })
+        .collect();
+
+    json!({ "anyOf": variants })
+}
+
+fn sentence_schema(langs: &[&str]) -> Value {
+    json!({ "type": "array", "items": lang_schema(langs) })
+}
+
+fn item_schema(langs: &[&str]) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "meaning": sentence_schema(langs),
+            "examples": { "type": "array", "items": sentence_schema(langs) }
+        },
+        "required": ["meaning", "examples"],
+        "additionalProperties": false
+    })
+}
+
+fn meaning_schema(langs: &[&str]) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "pos": { "type": "string" },
+            "meanings": { "type": "array", "items": item_schema(langs) }
+        },
+        "required": ["pos", "meanings"],
+        "additionalProperties": false
+    })
+}
+
+fn wrapped(name: &str, field: &str, list: Value) -> ResponseSchema {
+    ResponseSchema {
+        name: name.to_string(),
+        schema: json!({
+            "type": "object",
+            "properties": { field: list },
+            "required": [field],
+            "additionalProperties": false
+        }),
+    }
+}
+
+// `entry::Meanings` with English and the given language
+pub fn meanings_schema(lang: &TargetLang) -> ResponseSchema {
+    let langs = ["English", language_name(lang)];
+    wrapped(
+        "dictionary_entry",
+        "meanings",
+        json!({ "type": "array", "items": meaning_schema(&langs) }),
+    )
+}
+
+// example sentences as returned by search_example_sentences
+pub fn examples_schema(lang: &TargetLang) -> ResponseSchema {
+    let langs = ["English", language_name(lang)];
+    wrapped(
+        "example_sentences",
+        "examples",
+        json!({ "type": "array", "items": sentence_schema(&langs) }),
+    )
+}
+
+// The list inside a reply shaped by one of the schemas above. Replies from the few-shot
+// prompt are the bare list already and come back unchanged.
+pub fn unwrap(content: &str, field: &str) -> String {
+    match repair::parse_lenient::<Value>(content) {
+        Ok(Value::Object(mut object)) => match object.remove(field) {
+            Some(list) => list.to_string(),
+            None => content.to_string(),
+        },
+        _ => content.to_string(),
+    }
+}
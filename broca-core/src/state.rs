@@ -0,0 +1,1947 @@
+extern crate directories;
+use crate::anki::{self, AnkiExportReport, AnkiOptions};
+use crate::annotations::{self, Annotations, TagCount};
+use crate::collection::{self, Collection, CollectionSummary};
+use crate::conflict::{self, Conflict, ConflictVersion, Resolution};
+use crate::db::Database;
+use crate::entry::Entry;
+use crate::export::{self, ExportFormat, ExportRows, ImportReport};
+use crate::filename::{entry_filename, legacy_entry_filename};
+use crate::fulltext::{self, FullTextHit};
+use crate::git::{GitConfig, GitRepo, PullReport, Revision};
+use crate::lang;
+use crate::merge::merge_entries;
+use crate::migrations;
+use crate::provider::{self, DictionaryProvider};
+use crate::queue::{self, QueueConfig, QueueEvent, QueueStatus, TokenBucket};
+use crate::review::ReviewState;
+use crate::stream::SearchEvent;
+use crate::tts;
+use crate::watch::{self, VocabularyEvent, VocabularyWatcher};
+use anyhow::{anyhow, Ok, Result};
+use directories::ProjectDirs;
+use glob::glob;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use slugify::slugify;
+use std::fs::{create_dir_all, File};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum TargetLang {
+    Chinese,
+    Spanish,
+    Japanese,
+    Korean,
+    German,
+    French,
+    Portuguese,
+}
+
+impl TargetLang {
+    pub fn from_name(name: &str) -> Result<TargetLang> {
+        match name {
+            "Chinese" => Ok(TargetLang::Chinese),
+            "Spanish" => Ok(TargetLang::Spanish),
+            "Japanese" => Ok(TargetLang::Japanese),
+            "Korean" => Ok(TargetLang::Korean),
+            "German" => Ok(TargetLang::German),
+            "French" => Ok(TargetLang::French),
+            "Portuguese" => Ok(TargetLang::Portuguese),
+            &_ => Err(anyhow!("Unknown Target Language.")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PollyConfig {
+    pub(crate) aws_key: String,
+    pub(crate) aws_secret: String,
+    pub(crate) voice_id: String,
+}
+
+// Which chat model serves dictionary lookups. OpenAI uses `openai_token` from the config,
+// the others carry their own endpoint and credentials.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub enum ProviderConfig {
+    #[default]
+    OpenAI,
+    OpenAICompatible {
+        base_url: String,
+        model: String,
+        api_key: Option<String>,
+    },
+    Anthropic {
+        base_url: Option<String>,
+        model: String,
+        api_key: String,
+    },
+    // recorded responses instead of a model, see replay::ReplayProvider
+    Replay {
+        fixtures_path: String,
+    },
+}
+
+// Cheap to clone, clones share the provider and the queue runner flag.
+#[derive(Clone)]
+pub struct State {
+    workspace_path: String,
+    target_lang: TargetLang,
+    extra_target_langs: Vec<TargetLang>,
+    polly_config: Option<PollyConfig>,
+    provider: Arc<dyn DictionaryProvider>,
+    queue_config: QueueConfig,
+    queue_running: Arc<AtomicBool>,
+    db: Database,
+    // set when the workspace is versioned with git, see GitConfig
+    git: Option<GitRepo>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Config {
+    workspace_path: String,
+    openai_token: String,
+    target_lang: TargetLang,
+    // languages filled in on top of target_lang, which stays the language of the prompt
+    #[serde(default)]
+    extra_target_langs: Vec<TargetLang>,
+    polly_config: Option<PollyConfig>,
+    #[serde(default)]
+    provider: ProviderConfig,
+    #[serde(default)]
+    queue: QueueConfig,
+    // replies a lookup may take before malformed output counts as a failure
+    #[serde(default = "default_parse_attempts")]
+    parse_attempts: usize,
+    #[serde(default)]
+    git: GitConfig,
+}
+
+// Configs end up in logs, the Debug impls below leave keys and tokens out.
+fn redact(secret: &str) -> &'static str {
+    if secret.is_empty() {
+        ""
+    } else {
+        "<redacted>"
+    }
+}
+
+impl std::fmt::Debug for PollyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PollyConfig")
+            .field("aws_key", &redact(&self.aws_key))
+            .field("aws_secret", &redact(&self.aws_secret))
+            .field("voice_id", &self.voice_id)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for ProviderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderConfig::OpenAI => f.write_str("OpenAI"),
+            ProviderConfig::OpenAICompatible {
+                base_url,
+                model,
+                api_key,
+            } => f
+                .debug_struct("OpenAICompatible")
+                .field("base_url", base_url)
+                .field("model", model)
+                .field("api_key", &api_key.as_deref().map(redact))
+                .finish(),
+            ProviderConfig::Anthropic {
+                base_url,
+                model,
+                api_key,
+            } => f
+                .debug_struct("Anthropic")
+                .field("base_url", base_url)
+                .field("model", model)
+                .field("api_key", &redact(api_key))
+                .finish(),
+            ProviderConfig::Replay { fixtures_path } => f
+                .debug_struct("Replay")
+                .field("fixtures_path", fixtures_path)
+                .finish(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("workspace_path", &self.workspace_path)
+            .field("openai_token", &redact(&self.openai_token))
+            .field("target_lang", &self.target_lang)
+            .field("extra_target_langs", &self.extra_target_langs)
+            .field("polly_config", &self.polly_config)
+            .field("provider", &self.provider)
+            .field("queue", &self.queue)
+            .field("parse_attempts", &self.parse_attempts)
+            .field("git", &self.git)
+            .finish()
+    }
+}
+
+fn default_parse_attempts() -> usize {
+    provider::DEFAULT_PARSE_ATTEMPTS
+}
+
+fn record_file(conn: &Connection, query: &str, seconds: u64, filename: &str) -> Result<()> {
+    conn.prepare_cached("INSERT OR REPLACE INTO vocabulary_files(filename, query, timestamp) VALUES (?1, ?2, ?3);")?
+        .execute((filename, query, seconds))?;
+    Ok(())
+}
+
+// Inserts the entry unless the cache already holds a version at least as new,
+// keeping the full-text index in step with the vocabulary table. Returns whether the
+// row changed.
+fn upsert_vocabulary(
+    conn: &Connection,
+    entry: &Entry,
+    content: &str,
+    seconds: u64,
+    filename: &str,
+) -> Result<bool> {
+    let query = entry.query.to_lowercase();
+    record_file(conn, &query, seconds, filename)?;
+
+    let changed = conn
+        .prepare_cached("INSERT OR REPLACE INTO vocabulary(query, content, timestamp, filename) SELECT ?1, ?2, ?3, ?4 WHERE NOT EXISTS (SELECT * FROM vocabulary WHERE query = ?5 AND timestamp >= ?6);")?
+        .execute((query.clone(), content, seconds, filename, query.clone(), seconds))?;
+
+    if changed > 0 {
+        fulltext::index_entry(conn, entry)?;
+        annotations::index_annotations(conn, &query, &entry.annotations)?;
+    }
+
+    Ok(changed > 0)
+}
+
+// For entries we just wrote ourselves, which are the newest version even when the
+// file's modification time didn't move on from the previous write.
+fn replace_vocabulary(
+    conn: &Connection,
+    entry: &Entry,
+    content: &str,
+    seconds: u64,
+    filename: &str,
+) -> Result<()> {
+    record_file(conn, &entry.query.to_lowercase(), seconds, filename)?;
+    conn.prepare_cached("INSERT OR REPLACE INTO vocabulary(query, content, timestamp, filename) VALUES (?1, ?2, ?3, ?4);")?
+        .execute((entry.query.to_lowercase(), content, seconds, filename))?;
+    fulltext::index_entry(conn, entry)?;
+    annotations::index_annotations(conn, &entry.query, &entry.annotations)?;
+
+    Ok(())
+}
+
+pub fn audio_filename(content: &str) -> String {
+    let slug = slugify!(content, separator = "_");
+    format!("{}.mp3", slug.as_str())
+}
+
+// The entry in a file of vocabulary/ and the file's modification time.
+fn read_entry_file(path: &Path) -> Result<(Entry, u64)> {
+    let seconds = std::fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)?
+        .as_secs();
+    let file = File::open(path)?;
+    let entry: Entry = serde_json::from_reader(BufReader::new(file))?;
+    Ok((entry, seconds))
+}
+
+fn read_review_file(path: &Path) -> Result<ReviewState> {
+    let file = File::open(path)?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+fn mkdir_p<P: AsRef<Path>>(path: &P) -> Result<()> {
+    if let Err(e) = create_dir_all(path) {
+        if e.kind() != std::io::ErrorKind::AlreadyExists {
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+impl State {
+    pub fn new() -> Self {
+        State {
+            workspace_path: String::new(),
+            target_lang: TargetLang::Chinese,
+            extra_target_langs: Vec::new(),
+            polly_config: None,
+            queue_config: QueueConfig::default(),
+            queue_running: Arc::new(AtomicBool::new(false)),
+            db: Database::new(Path::new("cache.db")),
+            git: None,
+            provider: provider::from_config(
+                &ProviderConfig::OpenAI,
+                "",
+                provider::DEFAULT_PARSE_ATTEMPTS,
+            ),
+        }
+    }
+
+    // A state for an existing or new workspace that doesn't go through the config file,
+    // e.g. for tests against a temporary directory.
+    pub fn with_workspace(
+        workspace_path: &str,
+        target_lang: TargetLang,
+        provider: Arc<dyn DictionaryProvider>,
+    ) -> Result<Self> {
+        let mut state = State::new();
+        state.set_workspace_path(workspace_path);
+        state.target_lang = target_lang;
+        state.provider = provider;
+
+        let workspace_path = Path::new(workspace_path);
+        for dir in ["vocabulary", "audio", "review"] {
+            let path = workspace_path.join(dir);
+            if !path.exists() {
+                mkdir_p(&path)?;
+            }
+        }
+
+        state.open_cache()?;
+
+        Ok(state)
+    }
+
+    // Points the state at another workspace, connections to the old cache.db are left
+    // to the clones still using them.
+    fn set_workspace_path(&mut self, workspace_path: &str) {
+        self.workspace_path = workspace_path.to_string();
+        self.db = Database::new(&Path::new(workspace_path).join("cache.db"));
+    }
+
+    // Brings cache.db up to the current schema. cache.db only caches vocabulary/ and
+    // review/, so one that can't be migrated (corrupt, or written by a newer release)
+    // is deleted and created from scratch, returns true when that happened and the
+    // files need scanning again. Token usage and the lookup queue don't survive that.
+    fn init_db(&self) -> Result<bool> {
+        let workspace_path = Path::new(self.workspace_path.as_str());
+
+        let workspace_vocabulary_path_buf = PathBuf::new().join(workspace_path).join("vocabulary");
+
+        if !workspace_vocabulary_path_buf.exists() {
+            mkdir_p(&workspace_vocabulary_path_buf)?;
+        }
+
+        let migrated = self.db.get().and_then(|conn| migrations::migrate(&conn));
+
+        if let Err(message) = migrated {
+            eprintln!("Rebuilding cache.db: {}", message);
+
+            self.db.close_idle();
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = self.db.path().as_os_str().to_owned();
+                path.push(suffix);
+                let path = PathBuf::from(path);
+                if path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+            }
+
+            let conn = self.db.get()?;
+            migrations::migrate(&conn)?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    // Turns git versioning of the workspace on or off, creating the repository if
+    // needed. Changes made through the state are committed from then on.
+    pub fn set_git(&mut self, config: &GitConfig) -> Result<()> {
+        self.git = match config.enabled {
+            true => Some(GitRepo::open(Path::new(self.workspace_path.as_str()), config)?),
+            false => None,
+        };
+        Ok(())
+    }
+
+    fn git(&self) -> Result<&GitRepo> {
+        match &self.git {
+            Some(git) => Ok(git),
+            None => Err(anyhow!("Git is not enabled for this workspace.")),
+        }
+    }
+
+    // Commits `paths` (relative to the workspace) if git is enabled. The change is on
+    // disk already, so a failed commit is only logged and picked up by the next sync.
+    fn record_change(&self, paths: &[String], message: &str) {
+        if let Some(git) = &self.git {
+            if let Err(error) = git.commit(paths, message) {
+                eprintln!("Failed to commit \"{}\": {}", message, error);
+            }
+        }
+    }
+
+    fn open_cache(&self) -> Result<()> {
+        if self.init_db()? {
+            self.scan_vocabulary()?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_word(&self, query: &str) -> Result<String> {
+        let workspace_path = Path::new(self.workspace_path.as_str());
+
+        let workspace_vocabulary_path_buf = PathBuf::new().join(workspace_path).join("vocabulary");
+
+        let conn = self.db.get()?;
+
+        let filename = entry_filename(query);
+
+        // conflicting copies too, or the next scan brings the word back
+        let mut stmt = conn.prepare_cached("SELECT filename FROM vocabulary_files WHERE query = ?1;")?;
+        let copies = stmt
+            .query_map([query], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        // Files go first, a sync may already have removed some of them (even the main
+        // one, leaving just a copy). Should a removal fail the cache still has the word.
+        let review_path = workspace_path.join("review").join(&filename);
+        let mut file_paths = vec![workspace_vocabulary_path_buf.join(&filename), review_path];
+        file_paths.extend(copies.iter().map(|copy| workspace_vocabulary_path_buf.join(copy)));
+        for path in &file_paths {
+            if path.exists() {
+                std::fs::remove_file(path.as_path())?;
+            }
+        }
+
+        conn.execute("DELETE FROM vocabulary WHERE query = ?1;", &[query])?;
+        fulltext::remove_entry(&conn, query)?;
+        annotations::remove_annotations(&conn, query)?;
+        conn.execute("DELETE FROM vocabulary_files WHERE query = ?1;", [query])?;
+        conn.execute("DELETE FROM review WHERE query = ?1;", &[query])?;
+
+        let mut paths = vec![format!("vocabulary/{}", filename), format!("review/{}", filename)];
+        paths.extend(copies.iter().map(|copy| format!("vocabulary/{}", copy)));
+        self.record_change(&paths, &format!("Delete {}", query));
+
+        Ok(filename)
+    }
+
+    pub async fn say(&self, content: &str) -> Result<String> {
+        let new_filename = audio_filename(content);
+
+        let workspace_path = Path::new(self.workspace_path.as_str());
+
+        let workspace_audio_path_buf = PathBuf::new().join(workspace_path).join("audio");
+
+        let path = workspace_audio_path_buf.join(new_filename.as_str());
+
+        if path.exists() {
+            return Ok(String::from(path.to_str().unwrap()));
+        }
+
+        match &self.polly_config {
+            Some(polly_config) => {
+                tts::synthesize(polly_config, content, &path).await?;
+                Ok(String::from(path.to_str().unwrap()))
+            }
+            None => Err(anyhow!("No polly config found")),
+        }
+    }
+
+    // Writes the entry to vocabulary/ and refreshes its row in the cache, returning the
+    // serialized JSON as stored in the file.
+    fn save_entry(&self, entry: &Entry) -> Result<String> {
+        let workspace_path = Path::new(self.workspace_path.as_str());
+        let workspace_vocabulary_path_buf = PathBuf::new().join(workspace_path).join("vocabulary");
+
+        if !workspace_vocabulary_path_buf.exists() {
+            mkdir_p(&workspace_vocabulary_path_buf)?;
+        }
+
+        let new_filename = entry_filename(&entry.query);
+
+        let serialized = serde_json::to_string_pretty(entry)?;
+
+        let path = workspace_vocabulary_path_buf.join(&new_filename);
+
+        let mut file = File::create(path.as_path())?;
+
+        file.write_all(serialized.as_bytes())?;
+
+        let conn = self.db.get()?;
+
+        let seconds = std::fs::metadata(path.as_path())?
+            .modified()?
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+
+        replace_vocabulary(&conn, entry, &serialized, seconds, &new_filename)?;
+
+        Ok(serialized)
+    }
+
+    fn record_usage(&self, prompt: i64, completion: i64) -> Result<()> {
+        let conn = self.db.get()?;
+
+        conn.execute("UPDATE openai_usage SET prompt_tokens = prompt_tokens + ?1, completion_tokens = completion_tokens + ?2
+        WHERE id = (SELECT MIN(id) FROM openai_usage)
+          AND EXISTS (SELECT 1 FROM openai_usage);", (prompt, completion))?;
+
+        Ok(())
+    }
+
+    fn load_entry(&self, query: &str) -> Result<Option<Entry>> {
+        let conn = self.db.get()?;
+
+        let mut stmt = conn.prepare_cached("SELECT content FROM vocabulary WHERE query = ?1 LIMIT 1;")?;
+        let mut rows = stmt.query(&[query])?;
+
+        if let Some(row) = rows.next()? {
+            let content: String = row.get(0)?;
+            return Ok(Some(serde_json::from_str(&content)?));
+        }
+
+        Ok(None)
+    }
+
+    // Words already in the vocabulary book are served from the cache unless `refresh` is set.
+    // A refresh queries the model again and merges the answer into the saved entry so
+    // examples generated earlier are kept, see merge::merge_entries.
+    pub async fn search(
+        &self,
+        query: &str,
+        refresh: bool,
+        on_event: &(dyn Fn(SearchEvent) + Send + Sync),
+    ) -> Result<String> {
+        let query = query.trim().to_lowercase();
+
+        let existing = self.load_entry(&query)?;
+
+        if let (Some(existing), false) = (&existing, refresh) {
+            let serialized = serde_json::to_string_pretty(existing)?;
+            on_event(SearchEvent::Finished {
+                query: existing.query.clone(),
+                entry: serialized.clone(),
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                merge: None,
+            });
+            return Ok(serialized);
+        }
+
+        let source_lang = lang::detect_source_lang(&query, &self.target_langs());
+        if let Some(source_lang) = &source_lang {
+            eprintln!("reverse lookup from {:?}", source_lang);
+        }
+
+        let (prompt, completion, res) = self
+            .provider
+            .search_streaming(query.as_str(), &self.target_lang, source_lang.as_ref(), on_event)
+            .await?;
+
+        self.record_usage(prompt, completion)?;
+
+        let message = match existing {
+            Some(_) => format!("Refresh {}", query),
+            None => format!("Add {}", query),
+        };
+
+        let (mut res, merge) = match existing {
+            Some(existing) => {
+                let (merged, report) = merge_entries(existing, res);
+                eprintln!("merged {:?}", report);
+                (merged, Some(report))
+            }
+            None => {
+                let mut res = res;
+                res.annotations.added = Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+                (res, None)
+            }
+        };
+
+        self.fill_target_langs(&mut res).await;
+
+        let serialized = self.save_entry(&res)?;
+        self.record_change(&[format!("vocabulary/{}", entry_filename(&res.query))], &message);
+
+        on_event(SearchEvent::Finished {
+            query: res.query.clone(),
+            entry: serialized.clone(),
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            merge,
+        });
+
+        Ok(serialized)
+    }
+
+    fn target_langs(&self) -> Vec<TargetLang> {
+        let mut langs = vec![self.target_lang.clone()];
+        for lang in &self.extra_target_langs {
+            if !langs.contains(lang) {
+                langs.push(lang.clone());
+            }
+        }
+        langs
+    }
+
+    // Translates every meaning and example of the entry that has English text but no `lang`.
+    async fn translate_entry(&self, entry: &mut Entry, lang: &TargetLang) -> Result<()> {
+        fn missing(langs: &[crate::entry::Lang], lang: &TargetLang) -> Option<String> {
+            if langs.iter().any(|l| l.target_lang().as_ref() == Some(lang)) {
+                return None;
+            }
+            langs.iter().find_map(|l| match l {
+                crate::entry::Lang::English(text) => Some(text.clone()),
+                _ => None,
+            })
+        }
+
+        let mut texts = Vec::<String>::new();
+        // (meaning, item, example) each text goes back to, None for the item's meaning
+        let mut slots = Vec::<(usize, usize, Option<usize>)>::new();
+
+        for (mi, m) in entry.meanings.iter().enumerate() {
+            for (ii, item) in m.meanings.iter().enumerate() {
+                if let Some(text) = missing(&item.meaning, lang) {
+                    texts.push(text);
+                    slots.push((mi, ii, None));
+                }
+                for (ei, example) in item.examples.iter().enumerate() {
+                    if let Some(text) = missing(example, lang) {
+                        texts.push(text);
+                        slots.push((mi, ii, Some(ei)));
+                    }
+                }
+            }
+        }
+
+        if texts.is_empty() {
+            return Ok(());
+        }
+
+        let (prompt, completion, translations) = self.provider.translate(texts, lang).await?;
+        self.record_usage(prompt, completion)?;
+
+        for ((mi, ii, ei), translation) in slots.into_iter().zip(translations) {
+            let item = &mut entry.meanings[mi].meanings[ii];
+            let langs = match ei {
+                Some(ei) => &mut item.examples[ei],
+                None => &mut item.meaning,
+            };
+            langs.push(crate::openai::lang_text(lang, &translation));
+        }
+
+        Ok(())
+    }
+
+    // Best effort, a failed translation shouldn't lose the lookup it belongs to.
+    async fn fill_target_langs(&self, entry: &mut Entry) {
+        for lang in self.target_langs() {
+            if let Err(message) = self.translate_entry(entry, &lang).await {
+                eprintln!("Failed to translate {} into {:?}: {}", entry.query, lang, message);
+            }
+        }
+    }
+
+    pub async fn add_language(&self, query: &str, lang: &str) -> Result<String> {
+        let lang = TargetLang::from_name(lang)?;
+
+        let mut entry = match self.load_entry(query)? {
+            Some(entry) => entry,
+            None => return Err(anyhow!("{} is not in the vocabulary book.", query)),
+        };
+
+        self.translate_entry(&mut entry, &lang).await?;
+
+        let serialized = self.save_entry(&entry)?;
+        self.record_change(
+            &[format!("vocabulary/{}", entry_filename(&entry.query))],
+            &format!("Add {:?} to {}", lang, entry.query),
+        );
+
+        Ok(serialized)
+    }
+
+    pub async fn search_example_sentences(&self, entry_str: &str, meaning: &str) -> Result<String> {
+        let mut entry: crate::entry::Entry = serde_json::from_str(entry_str)?;
+
+        for e in &mut entry.meanings {
+            for m in &mut e.meanings {
+                for t in &m.meaning {
+                    if let crate::entry::Lang::English(eng_meaning) = t {
+                        if eng_meaning == meaning {
+                            let query = crate::openai::SentenceExampleQuery {
+                                query: entry.query.clone(),
+                                meaning: eng_meaning.clone(),
+                            };
+
+                            let (prompt, completion, res) = self
+                                .provider
+                                .search_example_sentences(&query, &self.target_lang)
+                                .await?;
+                            eprintln!("expand new sentences {:?}", &res);
+
+                            m.examples.extend(res);
+
+                            self.record_usage(prompt, completion)?;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.fill_target_langs(&mut entry).await;
+
+        let serialized = self.save_entry(&entry)?;
+        self.record_change(
+            &[format!("vocabulary/{}", entry_filename(&entry.query))],
+            &format!("Add examples to {}: {}", entry.query, meaning),
+        );
+
+        Ok(serialized)
+    }
+
+    pub fn load_config(&mut self) -> Result<Config> {
+        if let Some(proj_dirs) = ProjectDirs::from("com", "Epiphany", "Broca") {
+            let path = proj_dirs.config_dir();
+
+            eprintln!("{:?}", path);
+
+            let path_buf = PathBuf::new();
+            let config_file_path = path_buf.join(path).join("broca.conf.json");
+
+            eprintln!("{:?}", config_file_path);
+
+            let rs = config_file_path.exists();
+
+            if rs == true {
+                let mut file = File::open(config_file_path)?;
+                let mut file_buf: Vec<u8> = Vec::new();
+                file.read_to_end(&mut file_buf)?;
+
+                let config: Config = serde_json::from_slice(&file_buf)?;
+
+                eprintln!("exisiting config, {:?}", config);
+
+                self.set_workspace_path(&config.workspace_path);
+                self.target_lang = config.target_lang.clone();
+                self.extra_target_langs = config.extra_target_langs.clone();
+                self.polly_config = config.polly_config.clone();
+                self.provider = provider::from_config(
+                    &config.provider,
+                    &config.openai_token,
+                    config.parse_attempts,
+                );
+                self.queue_config = config.queue.clone();
+                if let Err(message) = self.set_git(&config.git) {
+                    eprintln!("Failed to open the workspace repository: {}", message);
+                }
+
+                // the app scans right after, but the command line goes straight to queries
+                if let Err(message) = self.open_cache() {
+                    eprintln!("Failed to open cache.db: {}", message);
+                }
+
+                return Ok(config);
+            }
+
+            // Lin: /home/alice/.config/barapp
+            // Win: C:\Users\Alice\AppData\Roaming\Foo Corp\Bar App\config
+            // Mac: /Users/Alice/Library/Application Support/com.Foo-Corp.Bar-App
+        }
+
+        Err(anyhow!("Not configed."))
+    }
+
+    pub fn load_word(&self, query: &str) -> Result<String> {
+        let conn = self.db.get()?;
+
+        let mut stmt = conn.prepare_cached("SELECT content FROM vocabulary WHERE query = ?1 LIMIT 1;")?;
+        let mut rows = stmt.query(&[query])?;
+
+        if let Some(row) = rows.next()? {
+            return Ok(row.get(0)?);
+        }
+
+        Err(anyhow!("{} is not in the vocabulary book.", query))
+    }
+
+    pub fn query_words(&self, query: &str, collection: Option<&str>) -> Result<Vec<String>> {
+        let conn = self.db.get()?;
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT query FROM vocabulary WHERE query LIKE :pattern
+            AND (:collection IS NULL OR query IN (SELECT collection_words.query FROM collection_words JOIN collections ON collections.filename = collection_words.filename WHERE collections.name = :collection COLLATE NOCASE))
+            ORDER BY timestamp DESC;",
+        )?;
+        let pattern = format!("%{}%", query);
+        let word_iter = stmt.query_map(
+            rusqlite::named_params! { ":pattern": pattern, ":collection": collection.map(str::trim) },
+            |row| row.get(0),
+        )?;
+
+        let mut result = Vec::<String>::new();
+        for word in word_iter {
+            result.push(word?);
+        }
+
+        Ok(result)
+    }
+
+    // Replaces the word's notes, tags, source and star with `annotations`. When it
+    // leaves out the date the word was added, the saved one is kept.
+    pub fn update_annotations(&self, query: &str, annotations: &Annotations) -> Result<String> {
+        let mut entry = match self.load_entry(query)? {
+            Some(entry) => entry,
+            None => return Err(anyhow!("{} is not in the vocabulary book.", query)),
+        };
+
+        let added = entry.annotations.added;
+        entry.annotations = annotations.clone();
+        entry.annotations.added = entry.annotations.added.or(added);
+        entry.annotations.normalize();
+
+        let serialized = self.save_entry(&entry)?;
+        self.record_change(
+            &[format!("vocabulary/{}", entry_filename(&entry.query))],
+            &format!("Annotate {}", entry.query),
+        );
+
+        Ok(serialized)
+    }
+
+    pub fn set_starred(&self, query: &str, starred: bool) -> Result<String> {
+        let mut entry = match self.load_entry(query)? {
+            Some(entry) => entry,
+            None => return Err(anyhow!("{} is not in the vocabulary book.", query)),
+        };
+
+        entry.annotations.starred = starred;
+
+        let serialized = self.save_entry(&entry)?;
+        let message = match starred {
+            true => format!("Star {}", entry.query),
+            false => format!("Unstar {}", entry.query),
+        };
+        self.record_change(&[format!("vocabulary/{}", entry_filename(&entry.query))], &message);
+
+        Ok(serialized)
+    }
+
+    pub fn list_tags(&self) -> Result<Vec<TagCount>> {
+        let conn = self.db.get()?;
+        annotations::list_tags(&conn)
+    }
+
+    // Words with the tag (case insensitive), newest first.
+    pub fn words_with_tag(&self, tag: &str) -> Result<Vec<String>> {
+        let conn = self.db.get()?;
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT vocabulary.query FROM vocabulary JOIN tags ON tags.query = vocabulary.query
+            WHERE tags.tag = ?1 COLLATE NOCASE ORDER BY vocabulary.timestamp DESC;",
+        )?;
+        let word_iter = stmt.query_map([tag.trim()], |row| row.get(0))?;
+
+        let mut result = Vec::<String>::new();
+        for word in word_iter {
+            result.push(word?);
+        }
+
+        Ok(result)
+    }
+
+    pub fn starred_words(&self) -> Result<Vec<String>> {
+        let conn = self.db.get()?;
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT query FROM vocabulary WHERE starred = 1 ORDER BY timestamp DESC;",
+        )?;
+        let word_iter = stmt.query_map((), |row| row.get(0))?;
+
+        let mut result = Vec::<String>::new();
+        for word in word_iter {
+            result.push(word?);
+        }
+
+        Ok(result)
+    }
+
+    pub fn list_collections(&self) -> Result<Vec<CollectionSummary>> {
+        let conn = self.db.get()?;
+        collection::list_collections(&conn)
+    }
+
+    pub fn load_collection(&self, name: &str) -> Result<Collection> {
+        match self.load_collection_files(name)? {
+            Some((c, _)) => Ok(c),
+            None => Err(anyhow!("There is no collection named {}.", name)),
+        }
+    }
+
+    pub fn create_collection(&self, name: &str, description: &str) -> Result<Collection> {
+        let mut c = Collection::new(name)?;
+        if self.load_collection_files(&c.name)?.is_some() {
+            return Err(anyhow!("There is already a collection named {}.", c.name));
+        }
+        c.description = description.trim().to_string();
+
+        self.save_collection(&c, &[], &format!("Create collection {}", c.name))?;
+
+        Ok(c)
+    }
+
+    pub fn delete_collection(&self, name: &str) -> Result<()> {
+        let (c, filenames) = match self.load_collection_files(name)? {
+            Some(found) => found,
+            None => return Err(anyhow!("There is no collection named {}.", name)),
+        };
+
+        let workspace_collections_path_buf =
+            PathBuf::new().join(self.workspace_path.as_str()).join("collections");
+        let conn = self.db.get()?;
+        for filename in &filenames {
+            let path = workspace_collections_path_buf.join(filename);
+            if path.exists() {
+                std::fs::remove_file(path.as_path())?;
+            }
+            collection::remove_collection_file(&conn, filename)?;
+        }
+
+        let paths: Vec<String> = filenames
+            .iter()
+            .map(|filename| format!("collections/{}", filename))
+            .collect();
+        self.record_change(&paths, &format!("Delete collection {}", c.name));
+
+        Ok(())
+    }
+
+    // Adds words to the collection, creating it if there is none by that name yet.
+    // The words don't have to be in the vocabulary book (yet).
+    pub fn add_to_collection(&self, name: &str, queries: &[String]) -> Result<Collection> {
+        let (mut c, filenames) = match self.load_collection_files(name)? {
+            Some(found) => found,
+            None => (Collection::new(name)?, Vec::new()),
+        };
+
+        let added = c.add(queries);
+        let message = match queries {
+            [query] => format!("Add {} to {}", query.trim().to_lowercase(), c.name),
+            _ => format!("Add {} words to {}", added, c.name),
+        };
+        self.save_collection(&c, &filenames, &message)?;
+
+        Ok(c)
+    }
+
+    pub fn remove_from_collection(&self, name: &str, queries: &[String]) -> Result<Collection> {
+        let (mut c, filenames) = match self.load_collection_files(name)? {
+            Some(found) => found,
+            None => return Err(anyhow!("There is no collection named {}.", name)),
+        };
+
+        let removed = c.remove(queries);
+        let message = match queries {
+            [query] => format!("Remove {} from {}", query.trim().to_lowercase(), c.name),
+            _ => format!("Remove {} words from {}", removed, c.name),
+        };
+        self.save_collection(&c, &filenames, &message)?;
+
+        Ok(c)
+    }
+
+    pub fn full_text_search(&self, query: &str, limit: Option<i64>) -> Result<Vec<FullTextHit>> {
+        let conn = self.db.get()?;
+
+        fulltext::search(&conn, query, limit.unwrap_or(50))
+    }
+
+    // Entries for the given queries, or the whole vocabulary book newest first when there are none.
+    fn load_entries(&self, queries: Option<&[String]>) -> Result<Vec<Entry>> {
+        let mut entries = Vec::<Entry>::new();
+
+        match queries {
+            Some(queries) => {
+                for query in queries {
+                    match self.load_entry(query)? {
+                        Some(entry) => entries.push(entry),
+                        None => return Err(anyhow!("{} is not in the vocabulary book.", query)),
+                    }
+                }
+            }
+            None => {
+                let conn = self.db.get()?;
+
+                let mut stmt = conn.prepare_cached("SELECT content FROM vocabulary ORDER BY timestamp DESC;")?;
+                let content_iter = stmt.query_map((), |row| row.get::<_, String>(0))?;
+
+                for content in content_iter {
+                    entries.push(serde_json::from_str(&content?)?);
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub fn export_anki(
+        &self,
+        path: &str,
+        queries: Option<Vec<String>>,
+        options: &AnkiOptions,
+    ) -> Result<AnkiExportReport> {
+        let entries = self.load_entries(queries.as_deref())?;
+        let audio_path = Path::new(self.workspace_path.as_str()).join("audio");
+
+        anki::export_apkg(&entries, &audio_path, Path::new(path), options)
+    }
+
+    pub fn export_entries(
+        &self,
+        path: &str,
+        queries: Option<Vec<String>>,
+        format: &ExportFormat,
+        rows: &ExportRows,
+    ) -> Result<usize> {
+        let entries = self.load_entries(queries.as_deref())?;
+
+        export::export_entries(&entries, Path::new(path), format, rows)
+    }
+
+    // Queues every word of the list that isn't in the vocabulary book yet,
+    // process_queue does the lookups.
+    pub fn import_words(&self, path: &str) -> Result<ImportReport> {
+        let words = export::read_word_list(Path::new(path))?;
+        let mut report = ImportReport::default();
+
+        for word in words {
+            if self.load_entry(&word)?.is_some() {
+                report.skipped.push(word);
+            } else {
+                report.queued.push(word);
+            }
+        }
+
+        let conn = self.db.get()?;
+        queue::enqueue(&conn, &report.queued)?;
+
+        Ok(report)
+    }
+
+    pub fn enqueue_words(&self, queries: Vec<String>) -> Result<QueueStatus> {
+        let queries: Vec<String> = queries
+            .iter()
+            .map(|query| query.trim().to_lowercase())
+            .filter(|query| !query.is_empty())
+            .collect();
+
+        let conn = self.db.get()?;
+        queue::enqueue(&conn, &queries)?;
+
+        queue::status(&conn)
+    }
+
+    pub fn queue_status(&self) -> Result<QueueStatus> {
+        let conn = self.db.get()?;
+
+        queue::status(&conn)
+    }
+
+    // Works through the lookup queue until nothing is pending. Only one runner works on a
+    // workspace at a time, calling this while one is active returns straight away and the
+    // active runner picks up the new jobs.
+    pub async fn process_queue(
+        &self,
+        on_event: &(dyn Fn(QueueEvent) + Send + Sync),
+    ) -> Result<QueueStatus> {
+        if self.queue_running.swap(true, Ordering::SeqCst) {
+            return self.queue_status();
+        }
+
+        let result = self.run_queue(on_event).await;
+        self.queue_running.store(false, Ordering::SeqCst);
+        result?;
+
+        self.queue_status()
+    }
+
+    async fn run_queue(&self, on_event: &(dyn Fn(QueueEvent) + Send + Sync)) -> Result<()> {
+        {
+            let conn = self.db.get()?;
+            queue::reset_running(&conn)?;
+        }
+
+        let workers = self.queue_config.workers.max(1);
+        let bucket = futures::lock::Mutex::new(TokenBucket::new(
+            workers as f64,
+            self.queue_config.requests_per_minute,
+        ));
+
+        let results =
+            futures::future::join_all((0..workers).map(|_| self.queue_worker(&bucket, on_event)))
+                .await;
+        for result in results {
+            result?;
+        }
+
+        Ok(())
+    }
+
+    async fn queue_worker(
+        &self,
+        bucket: &futures::lock::Mutex<TokenBucket>,
+        on_event: &(dyn Fn(QueueEvent) + Send + Sync),
+    ) -> Result<()> {
+        loop {
+            let (job, next_due) = {
+                let conn = self.db.get()?;
+                match queue::claim(&conn)? {
+                    Some(job) => (Some(job), None),
+                    None => (None, queue::next_due(&conn)?),
+                }
+            };
+
+            let job = match (job, next_due) {
+                (Some(job), _) => job,
+                // everything left is waiting for a retry
+                (None, Some(due)) => {
+                    let wait = (due - queue::now_millis()).max(10) as u64;
+                    tokio::time::sleep(Duration::from_millis(wait)).await;
+                    continue;
+                }
+                (None, None) => return Ok(()),
+            };
+
+            loop {
+                let wait = bucket.lock().await.take();
+                match wait {
+                    Some(wait) => tokio::time::sleep(wait).await,
+                    None => break,
+                }
+            }
+
+            on_event(QueueEvent::Started {
+                query: job.query.clone(),
+                attempt: job.attempts + 1,
+            });
+
+            let result = self.search(&job.query, false, &|_| {}).await;
+
+            let conn = self.db.get()?;
+            match result {
+                core::result::Result::Ok(entry) => {
+                    queue::finish(&conn, &job.query)?;
+                    on_event(QueueEvent::Finished {
+                        query: job.query,
+                        entry,
+                    });
+                }
+                Err(error) => {
+                    let message = error.to_string();
+                    if provider::is_transient(&error)
+                        && job.attempts + 1 < self.queue_config.max_attempts
+                    {
+                        let delay_millis = queue::backoff(&self.queue_config, job.attempts);
+                        eprintln!("Retrying {} in {}ms: {}", job.query, delay_millis, message);
+                        queue::retry(&conn, &job.query, delay_millis, &message)?;
+                        on_event(QueueEvent::Retrying {
+                            query: job.query,
+                            attempt: job.attempts + 1,
+                            delay_millis,
+                            error: message,
+                        });
+                    } else {
+                        queue::fail(&conn, &job.query, &message)?;
+                        on_event(QueueEvent::Failed {
+                            query: job.query,
+                            error: message,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Every word, or only those in `collection`, newest first.
+    pub fn fetch_all_words(&self, collection: Option<&str>) -> Result<Vec<String>> {
+        let conn = self.db.get()?;
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT query FROM vocabulary WHERE ?1 IS NULL OR query IN (SELECT collection_words.query FROM collection_words JOIN collections ON collections.filename = collection_words.filename WHERE collections.name = ?1 COLLATE NOCASE)
+            ORDER BY timestamp DESC;",
+        )?;
+        let word_iter = stmt.query_map([collection.map(str::trim)], |row| row.get(0))?;
+
+        let mut result = Vec::<String>::new();
+        for word in word_iter {
+            result.push(word?);
+        }
+
+        Ok(result)
+    }
+
+    pub fn scan_vocabulary(&self) -> Result<Vec<String>> {
+        let workspace_path = Path::new(self.workspace_path.as_str());
+
+        let workspace_vocabulary_path_buf = PathBuf::new().join(workspace_path).join("vocabulary");
+
+        if !workspace_vocabulary_path_buf.exists() {
+            mkdir_p(&workspace_vocabulary_path_buf)?;
+        }
+
+        let workspace_audio_path_buf = PathBuf::new().join(workspace_path).join("audio");
+
+        if !workspace_audio_path_buf.exists() {
+            mkdir_p(&workspace_audio_path_buf)?;
+        }
+
+        self.init_db()?;
+
+        let conn = self.db.get()?;
+
+        for entry in glob(
+            workspace_vocabulary_path_buf
+                .join("*.json")
+                .to_str()
+                .unwrap(),
+        )
+        .expect("Failed to read glob pattern")
+        {
+            match entry {
+                std::result::Result::Ok(path) => {
+                    // a half synced file shouldn't keep the rest out of the cache
+                    if let Err(message) = self.sync_vocabulary_file(&path) {
+                        eprintln!("Skipping {}: {}", path.display(), message);
+                    }
+                }
+                Err(e) => eprintln!("{:?}", e),
+            }
+        }
+
+        // files deleted while the app wasn't running
+        let mut stmt = conn.prepare_cached("SELECT filename FROM vocabulary_files;")?;
+        let filenames = stmt
+            .query_map((), |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        for filename in filenames {
+            let path = workspace_vocabulary_path_buf.join(&filename);
+            if !path.exists() {
+                self.sync_vocabulary_file(&path)?;
+            }
+        }
+
+        // caches created before the full-text index existed
+        let mut stmt = conn.prepare_cached(
+            "SELECT content FROM vocabulary WHERE query NOT IN (SELECT query FROM vocabulary_fts);",
+        )?;
+        let missing_iter = stmt.query_map((), |row| row.get::<_, String>(0))?;
+        for content in missing_iter {
+            let e: Entry = serde_json::from_str(&content?)?;
+            fulltext::index_entry(&conn, &e)?;
+        }
+
+        let workspace_review_path_buf = PathBuf::new().join(workspace_path).join("review");
+
+        if !workspace_review_path_buf.exists() {
+            mkdir_p(&workspace_review_path_buf)?;
+        }
+
+        for entry in glob(workspace_review_path_buf.join("*.json").to_str().unwrap())
+            .expect("Failed to read glob pattern")
+        {
+            match entry {
+                std::result::Result::Ok(path) => {
+                    // like vocabulary/, one bad file mustn't hold up the others
+                    let r = match read_review_file(&path) {
+                        std::result::Result::Ok(r) => r,
+                        Err(message) => {
+                            eprintln!("Skipping {}: {}", path.display(), message);
+                            continue;
+                        }
+                    };
+                    conn.execute("INSERT OR REPLACE INTO review(query, content, due, last_reviewed) SELECT ?1, ?2, ?3, ?4 WHERE NOT EXISTS (SELECT * FROM review WHERE query = ?5 AND last_reviewed >= ?6);", (r.query.clone(), serde_json::to_string(&r)?, r.due, r.last_reviewed, r.query.clone(), r.last_reviewed))?;
+                }
+                Err(e) => eprintln!("{:?}", e),
+            }
+        }
+
+        let renamed = self.rename_legacy_files()?;
+        if renamed > 0 {
+            eprintln!("Renamed {} files to the current naming scheme", renamed);
+        }
+
+        self.scan_collections()?;
+
+        let results = self.fetch_all_words(None);
+        results
+    }
+
+    // Brings the cache in line with one file of vocabulary/ after it was written or
+    // removed behind our back, e.g. by a sync client. None when the cache already agreed.
+    pub fn sync_vocabulary_file(&self, path: &Path) -> Result<Option<VocabularyEvent>> {
+        let filename = match path.file_name().and_then(|name| name.to_str()) {
+            Some(filename) => filename,
+            None => return Err(anyhow!("{} is not a vocabulary file.", path.display())),
+        };
+
+        let conn = self.db.get()?;
+
+        if !path.exists() {
+            let query: Option<String> = conn
+                .query_row(
+                    "SELECT query FROM vocabulary_files WHERE filename = ?1;",
+                    [filename],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let query = match query {
+                Some(query) => query,
+                None => return Ok(None),
+            };
+
+            conn.execute("DELETE FROM vocabulary_files WHERE filename = ?1;", [filename])?;
+
+            // another copy of the word is left, show that one if this file was shown
+            let other: Option<String> = conn
+                .query_row(
+                    "SELECT filename FROM vocabulary_files WHERE query = ?1 ORDER BY timestamp DESC LIMIT 1;",
+                    [&query],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(other) = other {
+                let shown: Option<String> = conn
+                    .query_row(
+                        "SELECT filename FROM vocabulary WHERE query = ?1;",
+                        [&query],
+                        |row| row.get(0),
+                    )
+                    .optional()?
+                    .flatten();
+                if shown.as_deref() != Some(filename) {
+                    return Ok(None);
+                }
+
+                let other_path = path.with_file_name(&other);
+                let (e, seconds) = read_entry_file(&other_path)?;
+                replace_vocabulary(&conn, &e, &serde_json::to_string(&e)?, seconds, &other)?;
+                return Ok(Some(VocabularyEvent::Changed { query }));
+            }
+
+            conn.execute("DELETE FROM vocabulary WHERE query = ?1;", [&query])?;
+            fulltext::remove_entry(&conn, &query)?;
+            annotations::remove_annotations(&conn, &query)?;
+            return Ok(Some(VocabularyEvent::Removed { query }));
+        }
+
+        let (e, seconds) = read_entry_file(path)?;
+        let query = e.query.to_lowercase();
+        let changed = upsert_vocabulary(&conn, &e, &serde_json::to_string(&e)?, seconds, filename)?;
+
+        let copies: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM vocabulary_files WHERE query = ?1;",
+            [&query],
+            |row| row.get(0),
+        )?;
+        if copies > 1 {
+            return Ok(Some(VocabularyEvent::Conflict { query }));
+        }
+
+        if changed {
+            return Ok(Some(VocabularyEvent::Changed { query }));
+        }
+
+        Ok(None)
+    }
+
+    // Moves files still named the way filename::legacy_entry_filename did to their
+    // filename::entry_filename, in vocabulary/ and review/. Other machines syncing the
+    // workspace see the renames and have nothing left to do. Files whose new name is
+    // taken are left alone, a second file for the word shows up as a conflict.
+    fn rename_legacy_files(&self) -> Result<usize> {
+        let workspace_path = Path::new(self.workspace_path.as_str());
+        let workspace_vocabulary_path_buf = PathBuf::new().join(workspace_path).join("vocabulary");
+        let workspace_review_path_buf = PathBuf::new().join(workspace_path).join("review");
+        let conn = self.db.get()?;
+        let mut renamed = 0;
+
+        let mut stmt = conn.prepare_cached("SELECT filename, query FROM vocabulary_files;")?;
+        let files = stmt
+            .query_map((), |row| rusqlite::Result::Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+
+        for (filename, query) in files {
+            let new_filename = entry_filename(&query);
+            if filename == new_filename || filename != legacy_entry_filename(&query) {
+                continue;
+            }
+
+            let new_path = workspace_vocabulary_path_buf.join(&new_filename);
+            if new_path.exists() {
+                continue;
+            }
+
+            std::fs::rename(workspace_vocabulary_path_buf.join(&filename), &new_path)?;
+            conn.execute(
+                "UPDATE vocabulary_files SET filename = ?1 WHERE filename = ?2;",
+                [&new_filename, &filename],
+            )?;
+            conn.execute(
+                "UPDATE vocabulary SET filename = ?1 WHERE filename = ?2;",
+                [&new_filename, &filename],
+            )?;
+            renamed += 1;
+        }
+
+        let mut stmt = conn.prepare_cached("SELECT query FROM review;")?;
+        let queries = stmt
+            .query_map((), |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        for query in queries {
+            let filename = legacy_entry_filename(&query);
+            let new_filename = entry_filename(&query);
+            let path = workspace_review_path_buf.join(&filename);
+            let new_path = workspace_review_path_buf.join(&new_filename);
+            if filename != new_filename && path.exists() && !new_path.exists() {
+                std::fs::rename(&path, &new_path)?;
+                renamed += 1;
+            }
+        }
+
+        Ok(renamed)
+    }
+
+    // Caches every file of collections/ and forgets the ones that are gone.
+    fn scan_collections(&self) -> Result<()> {
+        let workspace_collections_path_buf =
+            PathBuf::new().join(self.workspace_path.as_str()).join("collections");
+
+        for entry in glob(workspace_collections_path_buf.join("*.json").to_str().unwrap())
+            .expect("Failed to read glob pattern")
+        {
+            match entry {
+                std::result::Result::Ok(path) => {
+                    if let Err(message) = self.sync_collection_file(&path) {
+                        eprintln!("Skipping {}: {}", path.display(), message);
+                    }
+                }
+                Err(e) => eprintln!("{:?}", e),
+            }
+        }
+
+        let conn = self.db.get()?;
+        let mut stmt = conn.prepare_cached("SELECT filename FROM collections;")?;
+        let filenames = stmt
+            .query_map((), |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        for filename in filenames {
+            if !workspace_collections_path_buf.join(&filename).exists() {
+                collection::remove_collection_file(&conn, &filename)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // The collections/ counterpart of sync_vocabulary_file.
+    pub fn sync_collection_file(&self, path: &Path) -> Result<Option<VocabularyEvent>> {
+        let filename = match path.file_name().and_then(|name| name.to_str()) {
+            Some(filename) => filename,
+            None => return Ok(None),
+        };
+
+        let conn = self.db.get()?;
+        let cached: Option<(String, u64)> = conn
+            .query_row(
+                "SELECT name, timestamp FROM collections WHERE filename = ?1;",
+                [filename],
+                |row| rusqlite::Result::Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        if !path.exists() {
+            return match cached {
+                Some((name, _)) => {
+                    collection::remove_collection_file(&conn, filename)?;
+                    Ok(Some(VocabularyEvent::CollectionChanged { name }))
+                }
+                None => Ok(None),
+            };
+        }
+
+        let seconds = std::fs::metadata(path)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+        if let Some((_, timestamp)) = cached {
+            if timestamp >= seconds {
+                return Ok(None);
+            }
+        }
+
+        let c: Collection = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        collection::index_collection(&conn, filename, &c, seconds)?;
+
+        Ok(Some(VocabularyEvent::CollectionChanged { name: c.name }))
+    }
+
+    // The collection as held by all of its files, the newest one first, and their names.
+    fn load_collection_files(&self, name: &str) -> Result<Option<(Collection, Vec<String>)>> {
+        let workspace_collections_path_buf =
+            PathBuf::new().join(self.workspace_path.as_str()).join("collections");
+        let conn = self.db.get()?;
+        let filenames = collection::collection_files(&conn, name)?;
+
+        let mut merged: Option<Collection> = None;
+        for filename in &filenames {
+            let file = File::open(workspace_collections_path_buf.join(filename))?;
+            let c: Collection = serde_json::from_reader(BufReader::new(file))?;
+            match &mut merged {
+                Some(merged) => {
+                    merged.add(&c.words);
+                }
+                None => merged = Some(c),
+            }
+        }
+
+        Ok(merged.map(|c| (c, filenames)))
+    }
+
+    // Writes the collection under its own filename, replacing the files in `stale`
+    // (earlier names or conflicting copies), and commits it with `message`.
+    fn save_collection(&self, c: &Collection, stale: &[String], message: &str) -> Result<()> {
+        let workspace_collections_path_buf =
+            PathBuf::new().join(self.workspace_path.as_str()).join("collections");
+
+        if !workspace_collections_path_buf.exists() {
+            mkdir_p(&workspace_collections_path_buf)?;
+        }
+
+        let filename = entry_filename(&c.name);
+        let path = workspace_collections_path_buf.join(&filename);
+        let mut file = File::create(path.as_path())?;
+        file.write_all(serde_json::to_string_pretty(c)?.as_bytes())?;
+
+        let seconds = std::fs::metadata(path.as_path())?
+            .modified()?
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+
+        let conn = self.db.get()?;
+        let mut paths = vec![format!("collections/{}", filename)];
+        for other in stale {
+            if other == &filename {
+                continue;
+            }
+            let path = workspace_collections_path_buf.join(other);
+            if path.exists() {
+                std::fs::remove_file(path.as_path())?;
+            }
+            collection::remove_collection_file(&conn, other)?;
+            paths.push(format!("collections/{}", other));
+        }
+        collection::index_collection(&conn, &filename, c, seconds)?;
+
+        self.record_change(&paths, message);
+
+        Ok(())
+    }
+
+    fn load_conflict(&self, query: &str) -> Result<Option<Conflict>> {
+        let workspace_vocabulary_path_buf =
+            PathBuf::new().join(self.workspace_path.as_str()).join("vocabulary");
+        let conn = self.db.get()?;
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT filename FROM vocabulary_files WHERE query = ?1 ORDER BY timestamp DESC, filename;",
+        )?;
+        let filenames = stmt
+            .query_map([query], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        let mut versions = Vec::<ConflictVersion>::new();
+        for filename in filenames {
+            match read_entry_file(&workspace_vocabulary_path_buf.join(&filename)) {
+                std::result::Result::Ok((entry, modified)) => versions.push(ConflictVersion {
+                    filename,
+                    modified,
+                    entry,
+                }),
+                Err(message) => eprintln!("Skipping {}: {}", filename, message),
+            }
+        }
+
+        if versions.len() < 2 {
+            return Ok(None);
+        }
+
+        Ok(Some(Conflict {
+            query: query.to_string(),
+            versions,
+        }))
+    }
+
+    // Words with more than one file in vocabulary/, as found by the last scan or the watcher.
+    pub fn list_conflicts(&self) -> Result<Vec<Conflict>> {
+        let conn = self.db.get()?;
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT query FROM vocabulary_files GROUP BY query HAVING COUNT(*) > 1 ORDER BY query;",
+        )?;
+        let queries = stmt
+            .query_map((), |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        let mut conflicts = Vec::<Conflict>::new();
+        for query in queries {
+            if let Some(conflict) = self.load_conflict(&query)? {
+                conflicts.push(conflict);
+            }
+        }
+
+        Ok(conflicts)
+    }
+
+    // Saves the chosen or merged version under the word's own filename and deletes the
+    // other copies, returning the entry as saved.
+    pub fn resolve_conflict(&self, query: &str, resolution: &Resolution) -> Result<String> {
+        let conflict = match self.load_conflict(query)? {
+            Some(conflict) => conflict,
+            None => return Err(anyhow!("{} has no conflicting copies.", query)),
+        };
+
+        let entry = match resolution {
+            Resolution::Keep { filename } => {
+                match conflict.versions.iter().find(|v| &v.filename == filename) {
+                    Some(version) => version.entry.clone(),
+                    None => return Err(anyhow!("{} is not a copy of {}.", filename, query)),
+                }
+            }
+            Resolution::Merge => match conflict::merge_versions(&conflict.versions) {
+                Some(entry) => entry,
+                None => return Err(anyhow!("{} has no conflicting copies.", query)),
+            },
+        };
+
+        let serialized = self.save_entry(&entry)?;
+
+        let workspace_vocabulary_path_buf =
+            PathBuf::new().join(self.workspace_path.as_str()).join("vocabulary");
+        let kept = entry_filename(&entry.query);
+        let conn = self.db.get()?;
+        for version in &conflict.versions {
+            if version.filename == kept {
+                continue;
+            }
+            let path = workspace_vocabulary_path_buf.join(&version.filename);
+            if path.exists() {
+                std::fs::remove_file(path.as_path())?;
+            }
+            conn.execute("DELETE FROM vocabulary_files WHERE filename = ?1;", [&version.filename])?;
+        }
+
+        let paths: Vec<String> = conflict
+            .versions
+            .iter()
+            .map(|version| format!("vocabulary/{}", version.filename))
+            .collect();
+        let message = match resolution {
+            Resolution::Keep { filename } => format!("Resolve {}, keep {}", query, filename),
+            Resolution::Merge => format!("Resolve {}, merge {} versions", query, paths.len()),
+        };
+        self.record_change(&paths, &message);
+
+        Ok(serialized)
+    }
+
+    // Keeps the cache in line with vocabulary/ while the returned watcher is alive,
+    // on_event hears about every row that changed.
+    pub fn watch_vocabulary(
+        &self,
+        on_event: impl Fn(VocabularyEvent) + Send + 'static,
+    ) -> Result<VocabularyWatcher> {
+        let state = self.clone();
+        let workspace_path = Path::new(self.workspace_path.as_str());
+        let workspace_collections_path_buf = PathBuf::new().join(workspace_path).join("collections");
+
+        if !workspace_collections_path_buf.exists() {
+            mkdir_p(&workspace_collections_path_buf)?;
+        }
+
+        let dirs = [
+            PathBuf::new().join(workspace_path).join("vocabulary"),
+            workspace_collections_path_buf.clone(),
+        ];
+
+        watch::watch(&dirs, move |paths| {
+            for path in paths {
+                if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                    continue;
+                }
+                let synced = match path.parent() == Some(workspace_collections_path_buf.as_path()) {
+                    true => state.sync_collection_file(&path),
+                    false => state.sync_vocabulary_file(&path),
+                };
+                match synced {
+                    std::result::Result::Ok(Some(event)) => on_event(event),
+                    std::result::Result::Ok(None) => {}
+                    Err(message) => eprintln!("Failed to sync {}: {}", path.display(), message),
+                }
+            }
+        })
+    }
+
+    // Commits whatever hasn't been yet (reviews, files edited by hand), merges the
+    // remote's commits in and brings the cache up to date. Words both sides changed
+    // show up in list_conflicts.
+    pub fn pull_workspace(&self) -> Result<PullReport> {
+        let git = self.git()?;
+        git.commit_all("Record local changes")?;
+        let report = git.pull()?;
+        self.scan_vocabulary()?;
+        Ok(report)
+    }
+
+    pub fn push_workspace(&self) -> Result<()> {
+        let git = self.git()?;
+        git.commit_all("Record local changes")?;
+        git.push()
+    }
+
+    // Commits that added, changed or deleted the word, newest first.
+    pub fn word_history(&self, query: &str) -> Result<Vec<Revision>> {
+        self.git()?
+            .history(&format!("vocabulary/{}", entry_filename(query)))
+    }
+
+    // Saves the word as it was in `commit`, also bringing back a deleted word.
+    pub fn revert_word(&self, query: &str, commit: &str) -> Result<String> {
+        let filename = entry_filename(query);
+        let content = match self.git()?.file_at(commit, &format!("vocabulary/{}", filename))? {
+            Some(content) => content,
+            None => return Err(anyhow!("{} is not in the vocabulary book as of {}.", query, commit)),
+        };
+        let entry: Entry = serde_json::from_str(&content)?;
+
+        let serialized = self.save_entry(&entry)?;
+        self.record_change(
+            &[format!("vocabulary/{}", filename)],
+            &format!("Revert {} to {}", query, commit),
+        );
+
+        Ok(serialized)
+    }
+
+    // Due words of the whole vocabulary book, or of one collection for a study session
+    // scoped to it. Words that have never been reviewed are always due, after the ones
+    // whose schedule has expired.
+    pub fn fetch_due_words(&self, limit: Option<i64>, collection: Option<&str>) -> Result<Vec<String>> {
+        let conn = self.db.get()?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT vocabulary.query FROM vocabulary LEFT JOIN review ON review.query = vocabulary.query
+            WHERE (review.due IS NULL OR review.due <= ?1)
+            AND (?3 IS NULL OR vocabulary.query IN (SELECT collection_words.query FROM collection_words JOIN collections ON collections.filename = collection_words.filename WHERE collections.name = ?3 COLLATE NOCASE))
+            ORDER BY review.due IS NULL, review.due ASC, vocabulary.timestamp DESC LIMIT ?2;",
+        )?;
+        let word_iter = stmt.query_map(
+            (now, limit.unwrap_or(-1), collection.map(str::trim)),
+            |row| row.get(0),
+        )?;
+
+        let mut result = Vec::<String>::new();
+        for word in word_iter {
+            result.push(word?);
+        }
+
+        Ok(result)
+    }
+
+    pub fn load_review(&self, query: &str) -> Result<ReviewState> {
+        let conn = self.db.get()?;
+
+        let mut stmt = conn.prepare_cached("SELECT content FROM review WHERE query = ?1 LIMIT 1;")?;
+        let mut rows = stmt.query(&[query])?;
+
+        if let Some(row) = rows.next()? {
+            let content: String = row.get(0)?;
+            return Ok(serde_json::from_str(&content)?);
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(ReviewState::new(query, now))
+    }
+
+    pub fn submit_review(&self, query: &str, grade: u8) -> Result<ReviewState> {
+        let query = &query.to_lowercase();
+        let conn = self.db.get()?;
+
+        // a review file for a word that isn't there would sync around as an orphan
+        let known: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM vocabulary WHERE query = ?1);",
+            [query],
+            |row| row.get(0),
+        )?;
+        if !known {
+            return Err(anyhow!("{} is not in the vocabulary book.", query));
+        }
+
+        let workspace_path = Path::new(self.workspace_path.as_str());
+
+        let workspace_review_path_buf = PathBuf::new().join(workspace_path).join("review");
+
+        if !workspace_review_path_buf.exists() {
+            mkdir_p(&workspace_review_path_buf)?;
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        let mut review = self.load_review(query)?;
+        review.grade(grade, now)?;
+
+        let new_filename = entry_filename(query);
+
+        let serialized = serde_json::to_string_pretty(&review)?;
+
+        let path = workspace_review_path_buf.join(&new_filename);
+
+        let mut file = File::create(path.as_path())?;
+
+        file.write_all(serialized.as_bytes())?;
+
+        conn.execute("INSERT OR REPLACE INTO review(query, content, due, last_reviewed) VALUES (?1, ?2, ?3, ?4);", (review.query.clone(), serialized, review.due, review.last_reviewed))?;
+
+        self.record_change(
+            &[format!("review/{}", new_filename)],
+            &format!("Review {}: {}", query, grade),
+        );
+
+        Ok(review)
+    }
+
+    pub fn load_usage(&self) -> Result<[i64;2]> {
+        let conn = self.db.get()?;
+
+        let content = conn
+        .query_row(
+            "SELECT prompt_tokens, completion_tokens FROM openai_usage WHERE id = (SELECT MIN(id) FROM openai_usage)
+            AND EXISTS (SELECT 1 FROM openai_usage);",
+            (),
+            |row| rusqlite::Result::Ok([row.get(0).unwrap(), row.get(1).unwrap()]),
+        )
+        .unwrap();
+
+        Ok(content)
+    }
+
+    pub fn first_time_setup(
+        &mut self,
+        workspace_path_str: &str,
+        openai_token: &str,
+        target_lang: &str,
+        aws_key: Option<&str>,
+        aws_secret: Option<&str>,
+        provider: Option<ProviderConfig>,
+        extra_target_langs: Option<Vec<String>>,
+    ) -> Result<Config> {
+        if let Some(proj_dirs) = ProjectDirs::from("com", "Epiphany", "Broca") {
+            let config_dir_path = proj_dirs.config_dir();
+
+            let config_filename = "broca.conf.json";
+            let config_file_path = PathBuf::new().join(config_dir_path).join(config_filename);
+
+            let config = Config {
+                workspace_path: String::from(workspace_path_str),
+                openai_token: String::from(openai_token),
+                target_lang: TargetLang::from_name(target_lang)?,
+                extra_target_langs: extra_target_langs
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|name| TargetLang::from_name(name))
+                    .collect::<Result<Vec<TargetLang>>>()?,
+                polly_config: if aws_key.is_some() && aws_secret.is_some() {
+                    Some(PollyConfig {
+                        aws_key: aws_key.unwrap().to_string(),
+                        aws_secret: aws_secret.unwrap().to_string(),
+                        voice_id: "Olivia".to_string(),
+                    })
+                } else {
+                    None
+                },
+                provider: provider.unwrap_or_default(),
+                queue: QueueConfig::default(),
+                parse_attempts: provider::DEFAULT_PARSE_ATTEMPTS,
+                git: GitConfig::default(),
+            };
+
+            let serialized_config = serde_json::to_vec_pretty(&config)?;
+
+            if !config_dir_path.exists() {
+                mkdir_p(&config_dir_path)?;
+            }
+            let mut file = File::create(config_file_path)?;
+            file.write_all(&serialized_config)?;
+
+            self.set_workspace_path(&config.workspace_path);
+            self.target_lang = config.target_lang.clone();
+            self.extra_target_langs = config.extra_target_langs.clone();
+            self.polly_config = config.polly_config.clone();
+            self.provider = provider::from_config(
+                &config.provider,
+                &config.openai_token,
+                config.parse_attempts,
+            );
+            self.queue_config = config.queue.clone();
+
+            let workspace_path = Path::new(workspace_path_str);
+
+            if !workspace_path.exists() {
+                mkdir_p(&workspace_path)?;
+            }
+
+            let gitignore_path = workspace_path.join(".gitignore");
+
+            if !gitignore_path.exists() {
+                let mut gitignore_file = File::create(gitignore_path)?;
+                // cache.db plus its -wal and -shm files
+                gitignore_file.write_all(String::from("cache.db*").as_bytes())?;
+            }
+
+            let workspace_vocabulary_path_buf =
+                PathBuf::new().join(workspace_path).join("vocabulary");
+
+            if !workspace_vocabulary_path_buf.exists() {
+                mkdir_p(&workspace_vocabulary_path_buf)?;
+            }
+
+            let workspace_audio_path_buf = PathBuf::new().join(workspace_path).join("audio");
+
+            if !workspace_audio_path_buf.exists() {
+                mkdir_p(&workspace_audio_path_buf)?;
+            }
+
+            let workspace_review_path_buf = PathBuf::new().join(workspace_path).join("review");
+
+            if !workspace_review_path_buf.exists() {
+                mkdir_p(&workspace_review_path_buf)?;
+            }
+
+            self.open_cache()?;
+
+            return Ok(config.clone());
+        }
+
+        Err(anyhow!("No config directory found."))
+    }
+}
@@ -0,0 +1,267 @@
+use serde::Serialize;
+
+use crate::entry::{Item, Meaning};
+use crate::merge::MergeReport;
+
+// Progress of a streamed dictionary lookup. Items and meanings are reported as soon
+// as their JSON closes, `Finished` carries the entry exactly as it was persisted.
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchEvent {
+    Item {
+        query: String,
+        meaning_index: usize,
+        item_index: usize,
+        pos: Option<String>,
+        item: Item,
+    },
+    Meaning {
+        query: String,
+        index: usize,
+        meaning: Meaning,
+    },
+    Finished {
+        query: String,
+        entry: String,
+        prompt_tokens: i64,
+        completion_tokens: i64,
+        // what a refresh changed in the saved entry
+        merge: Option<MergeReport>,
+    },
+}
+
+// Incremental scanner over the streamed `Meanings` array,
+// i.e. `[{"pos": .., "meanings": [{"meaning": .., "examples": ..}, ..]}, ..]`,
+// or the same array wrapped as `{"meanings": [..]}` by schema::meanings_schema.
+// It only tracks bracket nesting outside of strings, so it never needs the whole document.
+pub struct EntryStreamParser {
+    buffer: String,
+    position: usize,
+    in_string: bool,
+    escaped: bool,
+    // opening bracket and its byte offset in buffer
+    stack: Vec<(char, usize)>,
+    meaning_count: usize,
+    item_count: usize,
+}
+
+pub enum Fragment {
+    Item {
+        meaning_index: usize,
+        item_index: usize,
+        pos: Option<String>,
+        item: Item,
+    },
+    Meaning {
+        index: usize,
+        meaning: Meaning,
+    },
+}
+
+impl EntryStreamParser {
+    pub fn new() -> Self {
+        EntryStreamParser {
+            buffer: String::new(),
+            position: 0,
+            in_string: false,
+            escaped: false,
+            stack: Vec::new(),
+            meaning_count: 0,
+            item_count: 0,
+        }
+    }
+
+    pub fn push(&mut self, delta: &str) -> Vec<Fragment> {
+        self.buffer.push_str(delta);
+
+        let buffer = std::mem::take(&mut self.buffer);
+        let mut fragments = Vec::<Fragment>::new();
+        let start = self.position;
+
+        for (offset, c) in buffer[start..].char_indices() {
+            let index = start + offset;
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if c == '\\' {
+                    self.escaped = true;
+                } else if c == '"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+
+            match c {
+                // quotes in prose around the JSON are not strings we care about
+                '"' if !self.stack.is_empty() => self.in_string = true,
+                '[' | '{' => self.stack.push((c, index)),
+                ']' | '}' => {
+                    let depth = self.stack.len();
+                    if let Some((_, open)) = self.stack.pop() {
+                        let base = self.base();
+                        if c == '}' && depth == base + 4 && self.is_item_path() {
+                            if let Some(fragment) = self.parse_item(&buffer, open, index) {
+                                fragments.push(fragment);
+                            }
+                            self.item_count += 1;
+                        } else if c == '}' && depth == base + 2 && self.stack[base].0 == '[' {
+                            let slice = &buffer[open..=index];
+                            if let Ok(meaning) = serde_json::from_str::<Meaning>(slice) {
+                                fragments.push(Fragment::Meaning {
+                                    index: self.meaning_count,
+                                    meaning,
+                                });
+                            }
+                            self.meaning_count += 1;
+                            self.item_count = 0;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.position = buffer.len();
+        self.buffer = buffer;
+
+        fragments
+    }
+
+    // 1 when the array sits inside a wrapping object, which is always the outermost bracket
+    fn base(&self) -> usize {
+        match self.stack.first() {
+            Some(('{', _)) => 1,
+            _ => 0,
+        }
+    }
+
+    // after popping an item object the stack is [ '[' meanings, '{' meaning, '[' items ]
+    fn is_item_path(&self) -> bool {
+        let base = self.base();
+        self.stack.len() == base + 3
+            && self.stack[base].0 == '['
+            && self.stack[base + 1].0 == '{'
+            && self.stack[base + 2].0 == '['
+    }
+
+    fn parse_item(&self, buffer: &str, open: usize, close: usize) -> Option<Fragment> {
+        let item: Item = serde_json::from_str(&buffer[open..=close]).ok()?;
+
+        // Close the enclosing meaning right after this item to learn its part of speech,
+        // which works whenever the model wrote "pos" before "meanings".
+        let meaning_open = self.stack[self.base() + 1].1;
+        let partial = format!("{}]}}", &buffer[meaning_open..=close]);
+        let pos = serde_json::from_str::<Meaning>(&partial)
+            .ok()
+            .map(|meaning| meaning.pos);
+
+        Some(Fragment::Item {
+            meaning_index: self.meaning_count,
+            item_index: self.item_count,
+            pos,
+            item,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::Lang;
+
+    const MEANINGS: &str = r#"[{"pos": "noun", "meanings": [{"meaning": [{"English": "A lucky find."}], "examples": [[{"English": "It was pure serendipity."}]]}, {"meaning": [{"English": "The knack of lucky finds."}], "examples": []}]}, {"pos": "adjective", "meanings": [{"meaning": [{"English": "Found by chance."}], "examples": []}]}]"#;
+
+    // (meaning index, item index, pos) of every item and (index, pos) of every meaning
+    type Summary = (Vec<(usize, usize, Option<String>)>, Vec<(usize, String)>);
+
+    fn summarize(fragments: Vec<Fragment>) -> Summary {
+        let mut items = Vec::new();
+        let mut meanings = Vec::new();
+        for fragment in fragments {
+            match fragment {
+                Fragment::Item {
+                    meaning_index,
+                    item_index,
+                    pos,
+                    ..
+                } => items.push((meaning_index, item_index, pos)),
+                Fragment::Meaning { index, meaning } => meanings.push((index, meaning.pos)),
+            }
+        }
+        (items, meanings)
+    }
+
+    fn feed(chunks: &[&str]) -> Vec<Fragment> {
+        let mut parser = EntryStreamParser::new();
+        chunks.iter().flat_map(|chunk| parser.push(chunk)).collect()
+    }
+
+    fn feed_in_pieces(text: &str, size: usize) -> Vec<Fragment> {
+        let chars: Vec<char> = text.chars().collect();
+        let chunks: Vec<String> = chars.chunks(size).map(|c| c.iter().collect()).collect();
+        feed(&chunks.iter().map(String::as_str).collect::<Vec<&str>>())
+    }
+
+    fn first_example(fragments: &[Fragment]) -> String {
+        match &fragments[0] {
+            Fragment::Item { item, .. } => match &item.examples[0][0] {
+                Lang::English(s) => s.clone(),
+                other => panic!("unexpected {:?}", other),
+            },
+            _ => panic!("expected an item first"),
+        }
+    }
+
+    #[test]
+    fn meanings_split_across_chunks_come_out_whole() {
+        let expected: Summary = (
+            vec![
+                (0, 0, Some("noun".to_string())),
+                (0, 1, Some("noun".to_string())),
+                (1, 0, Some("adjective".to_string())),
+            ],
+            vec![(0, "noun".to_string()), (1, "adjective".to_string())],
+        );
+
+        assert_eq!(summarize(feed(&[MEANINGS])), expected);
+        for size in [1, 2, 7, 64] {
+            assert_eq!(summarize(feed_in_pieces(MEANINGS, size)), expected, "chunks of {}", size);
+        }
+    }
+
+    #[test]
+    fn wrapped_meanings_and_surrounding_prose_are_handled() {
+        let text = format!("Here you go, \"as asked\": {{\"meanings\": {}}}", MEANINGS);
+        let (items, meanings) = summarize(feed_in_pieces(&text, 5));
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(meanings.len(), 2);
+    }
+
+    #[test]
+    fn brackets_inside_strings_are_not_counted() {
+        let text = r#"[{"pos": "noun", "meanings": [{"meaning": [{"English": "a {brace} or ]bracket["}], "examples": [[{"English": "Type } then ]."}]]}]}]"#;
+        let fragments = feed_in_pieces(text, 3);
+
+        assert_eq!(first_example(&fragments), "Type } then ].");
+        let (items, meanings) = summarize(fragments);
+        assert_eq!(items, vec![(0, 0, Some("noun".to_string()))]);
+        assert_eq!(meanings, vec![(0, "noun".to_string())]);
+    }
+
+    #[test]
+    fn escaped_quotes_at_a_chunk_boundary_stay_in_the_string() {
+        let head = r#"[{"pos": "noun", "meanings": [{"meaning": [{"English": "x"}], "examples": [[{"English": "She said \"#;
+        let tail = r#""}]\" twice."}]]}]}]"#;
+
+        // split right after the backslash, then right after the escaped quote
+        let fragments = feed(&[head, tail]);
+        assert_eq!(first_example(&fragments), "She said \"}]\" twice.");
+        assert_eq!(summarize(fragments).1.len(), 1);
+
+        let fragments = feed(&[&format!("{}\"", head), &tail[1..]]);
+        assert_eq!(first_example(&fragments), "She said \"}]\" twice.");
+        assert_eq!(summarize(fragments).1.len(), 1);
+    }
+}
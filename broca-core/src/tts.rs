@@ -0,0 +1,55 @@
+use anyhow::{anyhow, Result};
+use aws_sdk_polly::config::Credentials;
+use aws_sdk_polly::Client;
+use aws_types::region::Region;
+use std::io::Write;
+use std::path::Path;
+
+use crate::state::PollyConfig;
+
+// Synthesizes `text` with Amazon Polly and writes the mp3 to `path`.
+pub async fn synthesize(polly_config: &PollyConfig, text: &str, path: &Path) -> Result<()> {
+    let creds = Credentials::new(
+        &polly_config.aws_key,
+        &polly_config.aws_secret,
+        None,
+        None,
+        "self",
+    );
+
+    let conf = aws_sdk_polly::config::Config::builder()
+        .credentials_provider(creds)
+        .region(Region::new("us-west-2"))
+        .build();
+
+    let client = Client::from_conf(conf);
+
+    match client
+        .synthesize_speech()
+        .engine(aws_sdk_polly::types::Engine::Neural)
+        .output_format(aws_sdk_polly::types::OutputFormat::Mp3)
+        .text(text)
+        .language_code(aws_sdk_polly::types::LanguageCode::EnAu)
+        .voice_id(aws_sdk_polly::types::VoiceId::Olivia)
+        .send()
+        .await
+    {
+        core::result::Result::Ok(audio) => {
+            let buf = audio.audio_stream.collect().await?;
+            let mut file = std::fs::File::create(path)?;
+            file.write_all(&buf.to_vec())?;
+            file.flush()?;
+            eprintln!("Generated audio {}", path.display());
+
+            Ok(())
+        }
+        Err(message) => {
+            let service_error = message.into_service_error();
+            if let Some(message) = service_error.meta().message() {
+                eprintln!("Error: {}", message.to_string());
+                return Err(anyhow!(message.to_string()));
+            }
+            Err(anyhow!(service_error.to_string()))
+        }
+    }
+}
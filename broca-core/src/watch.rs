@@ -0,0 +1,46 @@
+use anyhow::Result;
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Sync clients tend to write a file in several steps (temp file, rename, touch),
+// events for a path within this window are delivered once.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VocabularyEvent {
+    Changed { query: String },
+    Removed { query: String },
+    // the word has more than one file now, see State::list_conflicts
+    Conflict { query: String },
+    // a collection was created, changed or deleted
+    CollectionChanged { name: String },
+}
+
+// Watching stops when this is dropped.
+pub struct VocabularyWatcher {
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+// Calls on_paths from the watcher's thread with the files of `dirs` that were created,
+// modified or removed.
+pub fn watch(
+    dirs: &[PathBuf],
+    mut on_paths: impl FnMut(Vec<PathBuf>) + Send + 'static,
+) -> Result<VocabularyWatcher> {
+    let mut debouncer = new_debouncer(DEBOUNCE, move |result: DebounceEventResult| match result {
+        Ok(events) => on_paths(events.into_iter().map(|event| event.path).collect()),
+        Err(error) => eprintln!("Watch error: {:?}", error),
+    })?;
+
+    for dir in dirs {
+        debouncer.watcher().watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    Ok(VocabularyWatcher {
+        _debouncer: debouncer,
+    })
+}
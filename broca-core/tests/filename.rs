@@ -0,0 +1,40 @@
+use broca_core::filename::{entry_filename, legacy_entry_filename, query_from_filename};
+
+#[test]
+fn queries_that_slugged_alike_get_their_own_files() {
+    let queries = ["co-op", "coop", "co op", "co_op", "Co op"];
+    let filenames: Vec<String> = queries.iter().map(|q| entry_filename(q)).collect();
+
+    assert_eq!(legacy_entry_filename("co-op"), legacy_entry_filename("co op"));
+    assert_eq!(filenames[0], "co-op.json");
+    assert_eq!(filenames[1], "coop.json");
+    assert_eq!(filenames[2], "co_op.json");
+    assert_eq!(filenames[3], "co%5Fop.json");
+    // the cache is case insensitive, so are filenames
+    assert_eq!(filenames[4], filenames[2]);
+}
+
+#[test]
+fn filenames_decode_back_to_the_query() {
+    for query in ["serendipity", "co-op", "co op", "rock 'n' roll", "偶然", "café", "con", "a.b"] {
+        let filename = entry_filename(query);
+        assert_eq!(query_from_filename(&filename).as_deref(), Some(query), "{}", filename);
+    }
+
+    assert_eq!(entry_filename("偶然"), "%E5%81%B6%E7%84%B6.json");
+    assert_eq!(entry_filename("con"), "%63on.json");
+    assert_eq!(query_from_filename("serendipity (conflicted copy).json"), None);
+    // not the canonical spelling of "coop"
+    assert_eq!(query_from_filename("%63oop.json"), None);
+}
+
+#[test]
+fn long_queries_stay_within_filesystem_limits() {
+    let a = "偶".repeat(40);
+    let b = format!("{}然", "偶".repeat(39));
+
+    let filename = entry_filename(&a);
+    assert!(filename.len() <= 205);
+    assert_ne!(filename, entry_filename(&b));
+    assert_eq!(query_from_filename(&filename), None);
+}
@@ -0,0 +1,668 @@
+// Drives State against a temporary workspace, with lookups answered by the
+// recorded responses in tests/fixtures instead of the network.
+
+use futures::executor::block_on;
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+use broca_core::anki::AnkiOptions;
+use broca_core::annotations::Annotations;
+use broca_core::conflict::Resolution;
+use broca_core::entry::{Entry, Lang};
+use broca_core::git::GitConfig;
+use broca_core::replay::ReplayProvider;
+use broca_core::state::{audio_filename, State, TargetLang};
+use broca_core::stream::SearchEvent;
+use broca_core::watch::VocabularyEvent;
+
+const EXAMPLE_MEANING: &str = "The occurrence of events by chance in a happy or beneficial way.";
+
+fn workspace() -> (TempDir, Arc<ReplayProvider>, State) {
+    let dir = tempfile::tempdir().unwrap();
+    let fixtures_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let provider = Arc::new(ReplayProvider::new(fixtures_path.to_str().unwrap()));
+    let state = State::with_workspace(
+        dir.path().to_str().unwrap(),
+        TargetLang::Chinese,
+        provider.clone(),
+    )
+    .unwrap();
+
+    (dir, provider, state)
+}
+
+fn search(state: &State, query: &str) -> anyhow::Result<Entry> {
+    let content = block_on(state.search(query, false, &|_| {}))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn examples(entry: &Entry) -> Vec<String> {
+    entry
+        .meanings
+        .iter()
+        .flat_map(|m| m.meanings.iter())
+        .flat_map(|item| item.examples.iter())
+        .filter_map(|example| {
+            example.iter().find_map(|l| match l {
+                Lang::English(s) => Some(s.clone()),
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn search_saves_entry_and_usage() {
+    let (dir, _provider, state) = workspace();
+
+    let entry = search(&state, "Serendipity ").unwrap();
+
+    assert_eq!(entry.query, "serendipity");
+    assert_eq!(entry.meanings[0].pos, "noun");
+    assert!(dir.path().join("vocabulary/serendipity.json").exists());
+    assert_eq!(state.fetch_all_words(None).unwrap(), vec!["serendipity"]);
+    assert_eq!(state.load_usage().unwrap(), [120, 80]);
+}
+
+#[test]
+fn search_reports_progress() {
+    let (_dir, _provider, state) = workspace();
+    let events = Mutex::new(Vec::<SearchEvent>::new());
+
+    block_on(state.search("serendipity", false, &|event| {
+        events.lock().unwrap().push(event)
+    }))
+    .unwrap();
+
+    let events = events.into_inner().unwrap();
+    assert!(matches!(events[0], SearchEvent::Item { meaning_index: 0, item_index: 0, .. }));
+    assert!(matches!(
+        events.last(),
+        Some(SearchEvent::Finished { prompt_tokens: 120, completion_tokens: 80, .. })
+    ));
+}
+
+#[test]
+fn search_serves_saved_entries_without_a_request() {
+    let (_dir, provider, state) = workspace();
+
+    search(&state, "serendipity").unwrap();
+    search(&state, "serendipity").unwrap();
+
+    assert_eq!(provider.requests(), vec!["serendipity"]);
+}
+
+#[test]
+fn search_repairs_fenced_reply() {
+    let (_dir, provider, state) = workspace();
+
+    let entry = search(&state, "ephemeral").unwrap();
+
+    assert_eq!(entry.meanings[0].pos, "adjective");
+    assert_eq!(examples(&entry), vec!["Fashions are ephemeral."]);
+    assert_eq!(provider.requests().len(), 1);
+}
+
+#[test]
+fn search_without_recording_fails_and_saves_nothing() {
+    let (dir, _provider, state) = workspace();
+
+    assert!(search(&state, "quixotic").is_err());
+    assert!(!dir.path().join("vocabulary/quixotic.json").exists());
+    assert!(state.fetch_all_words(None).unwrap().is_empty());
+}
+
+#[test]
+fn search_example_sentences_extends_meaning() {
+    let (_dir, _provider, state) = workspace();
+
+    let entry = search(&state, "serendipity").unwrap();
+    let content = block_on(state.search_example_sentences(
+        &serde_json::to_string(&entry).unwrap(),
+        EXAMPLE_MEANING,
+    ))
+    .unwrap();
+    let entry: Entry = serde_json::from_str(&content).unwrap();
+
+    assert_eq!(examples(&entry).len(), 3);
+    assert_eq!(state.load_word("serendipity").unwrap(), content);
+    assert_eq!(state.load_usage().unwrap(), [210, 140]);
+}
+
+#[test]
+fn scan_vocabulary_picks_up_synced_files() {
+    let (dir, _provider, state) = workspace();
+
+    // as if another machine had looked the word up and the file got synced over
+    let fixture = std::fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/serendipity.json"),
+    )
+    .unwrap();
+    let fixture: broca_core::replay::Fixture = serde_json::from_str(&fixture).unwrap();
+    let entry = Entry {
+        query: "serendipity".to_string(),
+        meanings: serde_json::from_str(&fixture.response.choices[0].message.content).unwrap(),
+        source_lang: None,
+        annotations: Default::default(),
+    };
+    std::fs::write(
+        dir.path().join("vocabulary/serendipity.json"),
+        serde_json::to_string_pretty(&entry).unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(state.scan_vocabulary().unwrap(), vec!["serendipity"]);
+
+    let hits = state.full_text_search("airport", None).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].query, "serendipity");
+}
+
+#[test]
+fn scan_vocabulary_renames_files_saved_under_the_old_scheme() {
+    let (dir, _provider, state) = workspace();
+
+    // slugify dropped the '!', the old name would be shared with "serendipity"
+    let mut entry = search(&state, "serendipity").unwrap();
+    state.delete_word("serendipity").unwrap();
+    entry.query = "serendipity!".to_string();
+    std::fs::write(
+        dir.path().join("vocabulary/serendipity.json"),
+        serde_json::to_string_pretty(&entry).unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(state.scan_vocabulary().unwrap(), vec!["serendipity!"]);
+    assert!(!dir.path().join("vocabulary/serendipity.json").exists());
+    assert!(dir.path().join("vocabulary/serendipity%21.json").exists());
+    assert!(state.load_word("serendipity!").is_ok());
+
+    // nothing left to rename, and the renamed file isn't seen as a second copy
+    assert_eq!(state.scan_vocabulary().unwrap(), vec!["serendipity!"]);
+    assert!(state.list_conflicts().unwrap().is_empty());
+}
+
+#[test]
+fn annotations_are_saved_indexed_and_survive_a_refresh() {
+    let (_dir, _provider, state) = workspace();
+
+    let entry = search(&state, "serendipity").unwrap();
+    assert!(entry.annotations.added.is_some());
+    assert!(entry.annotations.tags.is_empty());
+
+    let annotations = Annotations {
+        notes: "Walpole, 1754".to_string(),
+        tags: vec!["Travel".to_string(), " travel ".to_string(), "GRE".to_string()],
+        source_sentence: Some("It was pure serendipity that we met.".to_string()),
+        source_url: Some(String::new()),
+        ..Default::default()
+    };
+    state.update_annotations("serendipity", &annotations).unwrap();
+    let content = state.set_starred("serendipity", true).unwrap();
+    let saved: Entry = serde_json::from_str(&content).unwrap();
+    assert_eq!(saved.annotations.tags, vec!["Travel", "GRE"]);
+    assert_eq!(saved.annotations.source_url, None);
+    assert_eq!(saved.annotations.added, entry.annotations.added);
+    assert!(saved.annotations.starred);
+
+    search(&state, "ephemeral").unwrap();
+    assert_eq!(state.words_with_tag("travel").unwrap(), vec!["serendipity"]);
+    assert_eq!(state.starred_words().unwrap(), vec!["serendipity"]);
+    let tags: Vec<(String, i64)> = state
+        .list_tags()
+        .unwrap()
+        .into_iter()
+        .map(|t| (t.tag, t.words))
+        .collect();
+    assert_eq!(tags, vec![("GRE".to_string(), 1), ("Travel".to_string(), 1)]);
+
+    let refreshed = block_on(state.search("serendipity", true, &|_| {})).unwrap();
+    let refreshed: Entry = serde_json::from_str(&refreshed).unwrap();
+    assert_eq!(refreshed.annotations, saved.annotations);
+
+    state.delete_word("serendipity").unwrap();
+    assert!(state.list_tags().unwrap().is_empty());
+    assert!(state.starred_words().unwrap().is_empty());
+}
+
+#[test]
+fn annotations_in_synced_files_are_indexed() {
+    let (dir, _provider, state) = workspace();
+
+    // tagged on another device
+    let mut entry = search(&state, "serendipity").unwrap();
+    state.delete_word("serendipity").unwrap();
+    entry.annotations.tags = vec!["novel".to_string()];
+    std::fs::write(
+        dir.path().join("vocabulary/serendipity.json"),
+        serde_json::to_string_pretty(&entry).unwrap(),
+    )
+    .unwrap();
+
+    state.scan_vocabulary().unwrap();
+
+    assert_eq!(state.words_with_tag("Novel").unwrap(), vec!["serendipity"]);
+}
+
+#[test]
+fn submit_review_only_grades_words_in_the_vocabulary_book() {
+    let (dir, _provider, state) = workspace();
+    search(&state, "serendipity").unwrap();
+
+    let review = state.submit_review("Serendipity", 4).unwrap();
+    assert_eq!(review.query, "serendipity");
+    assert_eq!(state.load_review("serendipity").unwrap().repetitions, 1);
+
+    assert!(state.submit_review("ephemeral", 4).is_err());
+    assert!(!dir.path().join("review/ephemeral.json").exists());
+}
+
+#[test]
+fn scan_vocabulary_skips_unreadable_review_files() {
+    let (dir, _provider, state) = workspace();
+    search(&state, "serendipity").unwrap();
+    search(&state, "ephemeral").unwrap();
+    state.submit_review("ephemeral", 4).unwrap();
+
+    // half synced
+    std::fs::write(dir.path().join("review/serendipity.json"), "{\"query\": \"seren").unwrap();
+
+    assert_eq!(state.scan_vocabulary().unwrap().len(), 2);
+    assert_eq!(state.load_review("ephemeral").unwrap().repetitions, 1);
+}
+
+#[test]
+fn delete_word_removes_file_and_row() {
+    let (dir, _provider, state) = workspace();
+
+    search(&state, "serendipity").unwrap();
+    search(&state, "ephemeral").unwrap();
+
+    state.delete_word("serendipity").unwrap();
+
+    assert!(!dir.path().join("vocabulary/serendipity.json").exists());
+    assert_eq!(state.fetch_all_words(None).unwrap(), vec!["ephemeral"]);
+    assert!(state.full_text_search("airport", None).unwrap().is_empty());
+
+    // a rescan must not bring it back
+    assert_eq!(state.scan_vocabulary().unwrap(), vec!["ephemeral"]);
+}
+
+#[test]
+fn scan_vocabulary_drops_files_removed_elsewhere() {
+    let (dir, _provider, state) = workspace();
+
+    search(&state, "serendipity").unwrap();
+    search(&state, "ephemeral").unwrap();
+    std::fs::remove_file(dir.path().join("vocabulary/serendipity.json")).unwrap();
+
+    assert_eq!(state.scan_vocabulary().unwrap(), vec!["ephemeral"]);
+    assert!(state.full_text_search("airport", None).unwrap().is_empty());
+}
+
+#[test]
+fn watcher_follows_files_written_and_removed() {
+    let (dir, _provider, state) = workspace();
+    search(&state, "serendipity").unwrap();
+    let content = std::fs::read_to_string(dir.path().join("vocabulary/serendipity.json")).unwrap();
+    state.delete_word("serendipity").unwrap();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let _watcher = state
+        .watch_vocabulary(move |event| sender.send(event).unwrap())
+        .unwrap();
+    let next_event = || {
+        receiver
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .unwrap()
+    };
+
+    std::fs::write(dir.path().join("vocabulary/serendipity.json"), &content).unwrap();
+    assert!(matches!(next_event(), VocabularyEvent::Changed { query } if query == "serendipity"));
+    assert_eq!(state.fetch_all_words(None).unwrap(), vec!["serendipity"]);
+
+    std::fs::remove_file(dir.path().join("vocabulary/serendipity.json")).unwrap();
+    assert!(matches!(next_event(), VocabularyEvent::Removed { query } if query == "serendipity"));
+    assert!(state.fetch_all_words(None).unwrap().is_empty());
+}
+
+// Saves serendipity and drops a second copy with one more example next to it, the way
+// Dropbox does when two machines edit the file at once.
+fn conflicted_copy(dir: &TempDir, state: &State) -> String {
+    let mut entry = search(state, "serendipity").unwrap();
+    entry.meanings[0].meanings[0]
+        .examples
+        .push(vec![Lang::English("Finding that cafe was serendipity.".to_string())]);
+    let filename = "serendipity (conflicted copy).json".to_string();
+    std::fs::write(
+        dir.path().join("vocabulary").join(&filename),
+        serde_json::to_string_pretty(&entry).unwrap(),
+    )
+    .unwrap();
+    filename
+}
+
+#[test]
+fn scan_vocabulary_reports_conflicted_copies() {
+    let (dir, _provider, state) = workspace();
+    let copy = conflicted_copy(&dir, &state);
+
+    assert_eq!(state.scan_vocabulary().unwrap(), vec!["serendipity"]);
+
+    let conflicts = state.list_conflicts().unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].query, "serendipity");
+    let mut filenames: Vec<&str> = conflicts[0]
+        .versions
+        .iter()
+        .map(|v| v.filename.as_str())
+        .collect();
+    filenames.sort();
+    assert_eq!(filenames, vec![copy.as_str(), "serendipity.json"]);
+}
+
+#[test]
+fn resolve_conflict_merges_and_removes_the_copy() {
+    let (dir, _provider, state) = workspace();
+    let copy = conflicted_copy(&dir, &state);
+    state.scan_vocabulary().unwrap();
+
+    let content = state.resolve_conflict("serendipity", &Resolution::Merge).unwrap();
+    let entry: Entry = serde_json::from_str(&content).unwrap();
+
+    assert!(examples(&entry).contains(&"Finding that cafe was serendipity.".to_string()));
+    assert!(!dir.path().join("vocabulary").join(&copy).exists());
+    assert!(dir.path().join("vocabulary/serendipity.json").exists());
+    assert!(state.list_conflicts().unwrap().is_empty());
+    assert_eq!(state.scan_vocabulary().unwrap(), vec!["serendipity"]);
+}
+
+#[test]
+fn delete_word_removes_copies_left_after_the_file_was_synced_away() {
+    let (dir, _provider, state) = workspace();
+    let copy = conflicted_copy(&dir, &state);
+    state.scan_vocabulary().unwrap();
+    state.submit_review("serendipity", 4).unwrap();
+    std::fs::remove_file(dir.path().join("vocabulary/serendipity.json")).unwrap();
+
+    state.delete_word("serendipity").unwrap();
+
+    assert!(!dir.path().join("vocabulary").join(&copy).exists());
+    assert!(!dir.path().join("review/serendipity.json").exists());
+    assert!(state.list_conflicts().unwrap().is_empty());
+    assert!(state.scan_vocabulary().unwrap().is_empty());
+}
+
+#[test]
+fn resolve_conflict_keeps_the_chosen_version() {
+    let (dir, _provider, state) = workspace();
+    conflicted_copy(&dir, &state);
+    state.scan_vocabulary().unwrap();
+
+    let resolution = Resolution::Keep {
+        filename: "serendipity.json".to_string(),
+    };
+    let content = state.resolve_conflict("serendipity", &resolution).unwrap();
+    let entry: Entry = serde_json::from_str(&content).unwrap();
+
+    assert!(!examples(&entry).contains(&"Finding that cafe was serendipity.".to_string()));
+    assert!(state.list_conflicts().unwrap().is_empty());
+}
+
+fn reopen(dir: &TempDir, provider: Arc<ReplayProvider>) -> State {
+    State::with_workspace(dir.path().to_str().unwrap(), TargetLang::Chinese, provider).unwrap()
+}
+
+#[test]
+fn cache_from_a_newer_release_is_rebuilt_from_files() {
+    let (dir, provider, state) = workspace();
+    search(&state, "serendipity").unwrap();
+    drop(state);
+
+    let conn = rusqlite::Connection::open(dir.path().join("cache.db")).unwrap();
+    conn.pragma_update(None, "user_version", 999).unwrap();
+    drop(conn);
+
+    let state = reopen(&dir, provider);
+
+    assert_eq!(state.fetch_all_words(None).unwrap(), vec!["serendipity"]);
+    let conn = rusqlite::Connection::open(dir.path().join("cache.db")).unwrap();
+    assert_eq!(
+        broca_core::migrations::user_version(&conn).unwrap(),
+        broca_core::migrations::latest_version()
+    );
+}
+
+#[test]
+fn corrupt_cache_is_rebuilt_from_files() {
+    let (dir, provider, state) = workspace();
+    search(&state, "serendipity").unwrap();
+    drop(state);
+
+    std::fs::write(dir.path().join("cache.db"), "not a database").unwrap();
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(dir.path().join(format!("cache.db{}", suffix)));
+    }
+
+    let state = reopen(&dir, provider);
+
+    assert!(state.load_word("serendipity").is_ok());
+}
+
+#[test]
+fn export_apkg_packs_notes_and_cached_audio() {
+    let (dir, _provider, state) = workspace();
+    search(&state, "serendipity").unwrap();
+
+    // as left behind by `say`, which isn't called here so the test stays offline
+    let audio = audio_filename("serendipity");
+    std::fs::create_dir_all(dir.path().join("audio")).unwrap();
+    std::fs::write(dir.path().join("audio").join(&audio), b"ID3 not really an mp3").unwrap();
+
+    let output = dir.path().join("broca.apkg");
+    let report = state
+        .export_anki(output.to_str().unwrap(), None, &AnkiOptions::default())
+        .unwrap();
+    assert_eq!((report.notes, report.media), (1, 1));
+
+    let mut zip = zip::ZipArchive::new(std::fs::File::open(&output).unwrap()).unwrap();
+
+    let mut media = String::new();
+    zip.by_name("media").unwrap().read_to_string(&mut media).unwrap();
+    let media: serde_json::Value = serde_json::from_str(&media).unwrap();
+    assert_eq!(media, serde_json::json!({ "0": audio }));
+
+    let mut mp3 = Vec::new();
+    zip.by_name("0").unwrap().read_to_end(&mut mp3).unwrap();
+    assert_eq!(mp3, b"ID3 not really an mp3");
+
+    let collection_path = dir.path().join("collection.anki2");
+    let mut collection = Vec::new();
+    zip.by_name("collection.anki2").unwrap().read_to_end(&mut collection).unwrap();
+    std::fs::write(&collection_path, collection).unwrap();
+
+    let conn = rusqlite::Connection::open(&collection_path).unwrap();
+    let (fields, sort_field): (String, String) = conn
+        .query_row("SELECT flds, sfld FROM notes;", (), |row| {
+            rusqlite::Result::Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap();
+    let fields: Vec<&str> = fields.split('\x1f').collect();
+
+    assert_eq!(sort_field, "serendipity");
+    assert_eq!(fields.len(), 4);
+    assert_eq!(fields[0], "serendipity");
+    assert!(fields[1].contains(EXAMPLE_MEANING));
+    assert_eq!(fields[3], format!("[sound:{}]", audio));
+}
+
+fn with_git(dir: &TempDir, state: &mut State, remote: Option<&Path>) {
+    let config = GitConfig {
+        enabled: true,
+        remote: remote.map(|path| path.to_str().unwrap().to_string()),
+    };
+    state.set_git(&config).unwrap();
+    assert!(dir.path().join(".git").exists());
+}
+
+#[test]
+fn git_history_lists_each_change_and_reverts_to_it() {
+    let (dir, _provider, mut state) = workspace();
+    with_git(&dir, &mut state, None);
+
+    let entry = search(&state, "serendipity").unwrap();
+    block_on(state.search_example_sentences(
+        &serde_json::to_string(&entry).unwrap(),
+        EXAMPLE_MEANING,
+    ))
+    .unwrap();
+    search(&state, "ephemeral").unwrap();
+    state.delete_word("serendipity").unwrap();
+
+    let history = state.word_history("serendipity").unwrap();
+    let summaries: Vec<&str> = history.iter().map(|r| r.summary.as_str()).collect();
+    assert_eq!(
+        summaries,
+        vec![
+            "Delete serendipity",
+            &format!("Add examples to serendipity: {}", EXAMPLE_MEANING),
+            "Add serendipity",
+        ]
+    );
+    assert!(history[0].deleted);
+
+    // back to before the examples were added, which also undoes the delete
+    let content = state.revert_word("serendipity", &history[2].commit).unwrap();
+    let entry: Entry = serde_json::from_str(&content).unwrap();
+    assert_eq!(examples(&entry).len(), 1);
+    assert_eq!(state.load_word("serendipity").unwrap(), content);
+    assert_eq!(state.word_history("serendipity").unwrap().len(), 4);
+}
+
+#[test]
+fn git_commits_graded_reviews() {
+    let (dir, _provider, mut state) = workspace();
+    with_git(&dir, &mut state, None);
+    search(&state, "serendipity").unwrap();
+
+    state.submit_review("serendipity", 4).unwrap();
+
+    let repo = git2::Repository::open(dir.path()).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("Review serendipity: 4"));
+    assert!(head.tree().unwrap().get_path(Path::new("review/serendipity.json")).is_ok());
+}
+
+#[test]
+fn git_pull_brings_in_words_and_keeps_both_sides_of_an_edit() {
+    let remote = tempfile::tempdir().unwrap();
+    git2::Repository::init_bare(remote.path()).unwrap();
+
+    let (dir_a, _provider, mut a) = workspace();
+    with_git(&dir_a, &mut a, Some(remote.path()));
+    let (dir_b, _provider, mut b) = workspace();
+    with_git(&dir_b, &mut b, Some(remote.path()));
+
+    search(&a, "serendipity").unwrap();
+    a.push_workspace().unwrap();
+
+    let report = b.pull_workspace().unwrap();
+    assert_eq!(report.received, 1);
+    assert_eq!(b.fetch_all_words(None).unwrap(), vec!["serendipity"]);
+
+    // both edit the word before seeing the other's change
+    let entry = search(&a, "serendipity").unwrap();
+    block_on(a.search_example_sentences(
+        &serde_json::to_string(&entry).unwrap(),
+        EXAMPLE_MEANING,
+    ))
+    .unwrap();
+    a.push_workspace().unwrap();
+    conflicted_copy(&dir_b, &b);
+    std::fs::rename(
+        dir_b.path().join("vocabulary/serendipity (conflicted copy).json"),
+        dir_b.path().join("vocabulary/serendipity.json"),
+    )
+    .unwrap();
+    search(&b, "ephemeral").unwrap();
+
+    assert!(b.push_workspace().is_err());
+    let report = b.pull_workspace().unwrap();
+    assert_eq!(report.conflicted_copies.len(), 1);
+    b.push_workspace().unwrap();
+
+    let mut words = b.fetch_all_words(None).unwrap();
+    words.sort();
+    assert_eq!(words, vec!["ephemeral", "serendipity"]);
+    let conflicts = b.list_conflicts().unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].versions.len(), 2);
+
+    let content = b.resolve_conflict("serendipity", &Resolution::Merge).unwrap();
+    let entry: Entry = serde_json::from_str(&content).unwrap();
+    assert!(examples(&entry).contains(&"Finding that cafe was serendipity.".to_string()));
+    assert_eq!(examples(&entry).len(), 4);
+}
+
+#[test]
+fn collections_scope_word_lists() {
+    let (dir, _provider, state) = workspace();
+    search(&state, "serendipity").unwrap();
+    search(&state, "ephemeral").unwrap();
+
+    let words = vec!["Serendipity".to_string(), "petrichor".to_string()];
+    state.add_to_collection("GRE list", &words).unwrap();
+    assert!(dir.path().join("collections/gre_list.json").exists());
+    assert!(state.create_collection("gre list", "").is_err());
+
+    assert_eq!(state.fetch_all_words(Some("gre list")).unwrap(), vec!["serendipity"]);
+    assert_eq!(state.query_words("e", Some("GRE list")).unwrap(), vec!["serendipity"]);
+    assert_eq!(state.fetch_due_words(None, Some("GRE list")).unwrap(), vec!["serendipity"]);
+    assert_eq!(state.fetch_all_words(None).unwrap().len(), 2);
+    // not looked up yet, but still a member
+    assert_eq!(state.load_collection("GRE list").unwrap().words, vec!["serendipity", "petrichor"]);
+    let collections = state.list_collections().unwrap();
+    assert_eq!(collections.len(), 1);
+    assert_eq!((collections[0].name.as_str(), collections[0].words), ("GRE list", 1));
+
+    state.remove_from_collection("GRE list", &["serendipity".to_string()]).unwrap();
+    assert!(state.fetch_all_words(Some("GRE list")).unwrap().is_empty());
+
+    state.delete_collection("GRE list").unwrap();
+    assert!(!dir.path().join("collections/gre_list.json").exists());
+    assert!(state.list_collections().unwrap().is_empty());
+    assert!(state.load_collection("GRE list").is_err());
+}
+
+#[test]
+fn synced_collection_copies_are_combined() {
+    let (dir, _provider, state) = workspace();
+    search(&state, "serendipity").unwrap();
+    search(&state, "ephemeral").unwrap();
+
+    std::fs::create_dir_all(dir.path().join("collections")).unwrap();
+    for (filename, word) in [
+        ("book%3A_dune.json", "serendipity"),
+        ("book%3A_dune (conflicted copy).json", "ephemeral"),
+    ] {
+        std::fs::write(
+            dir.path().join("collections").join(filename),
+            format!("{{\"name\": \"Book: Dune\", \"words\": [\"{}\"]}}", word),
+        )
+        .unwrap();
+    }
+
+    state.scan_vocabulary().unwrap();
+    let mut words = state.fetch_all_words(Some("book: dune")).unwrap();
+    words.sort();
+    assert_eq!(words, vec!["ephemeral", "serendipity"]);
+
+    // the next change writes one file with every member
+    state.add_to_collection("Book: Dune", &["arrakis".to_string()]).unwrap();
+    assert!(!dir.path().join("collections/book%3A_dune (conflicted copy).json").exists());
+    let mut members = state.load_collection("book: dune").unwrap().words;
+    members.sort();
+    assert_eq!(members, vec!["arrakis", "ephemeral", "serendipity"]);
+}
@@ -24,8 +24,11 @@ export function AppContextProvider(props) {
     }
   }
 
+  let lastQuery = null;
+
   async function narrowDown(query) {
     console.log(query);
+    lastQuery = query;
     try {
       if (query) {
         const words = await tauri_invoke('query_words', { query: query });
@@ -41,6 +44,11 @@ export function AppContextProvider(props) {
     }
   }
 
+  // re-runs the current filter, e.g. after the vocabulary folder changed
+  async function refreshItems() {
+    await narrowDown(lastQuery);
+  }
+
   async function dismissLoading() {
     await refreshUsage();
     setIsLoading(false);
@@ -53,7 +61,7 @@ export function AppContextProvider(props) {
   return (
     <AppContext.Provider value={{
       configured: { configured, setConfigured },
-      narrowDown, allItems, setAllItems,
+      narrowDown, refreshItems, allItems, setAllItems,
       detail: { detail, setDetail },
       showConfig: { showConfig, setShowConfig },
       error: { error, setError },
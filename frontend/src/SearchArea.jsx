@@ -1,6 +1,6 @@
 import styles from './SearchArea.module.css';
 import { useAppContext } from "./AppContext";
-import { tauri_invoke, tauri_dialog } from './tauri';
+import { tauri_invoke, tauri_dialog, tauri_listen } from './tauri';
 
 import { createSignal } from 'solid-js';
 
@@ -24,10 +24,32 @@ function SearchArea(prop) {
             e.preventDefault();
             loading.setIsLoading(true);
             const query = e.target.value;
+            // shift+enter looks the word up again even if it's already in the vocabulary book
+            const refresh = e.shiftKey;
             console.log(query);
             setTimeout(async () => {
+                // fill in the detail view while the lookup is still streaming
+                let partial = { query: query.toLowerCase(), meanings: [] };
+                const unlisten = await tauri_listen('search-progress', (event) => {
+                    const progress = event.payload;
+                    if (progress.type === 'item') {
+                        if (!partial.meanings[progress.meaning_index]) {
+                            partial.meanings[progress.meaning_index] = { pos: progress.pos || '', meanings: [] };
+                        }
+                        partial.meanings[progress.meaning_index].meanings[progress.item_index] = progress.item;
+                    }
+                    else if (progress.type === 'meaning') {
+                        partial.meanings[progress.index] = progress.meaning;
+                    }
+                    else {
+                        return;
+                    }
+                    partial = { ...partial, meanings: [...partial.meanings] };
+                    detail.setDetail(partial);
+                    loading.setIsLoading(false);
+                });
                 try {
-                    const word = await tauri_invoke('search', { query: query });
+                    const word = await tauri_invoke('search', { query: query, refresh: refresh });
                     const parsed = JSON.parse(word);
                     console.log(parsed);
 
@@ -40,6 +62,7 @@ function SearchArea(prop) {
                     loading.dismissLoading(false);
                     error.setError(err);
                 }
+                unlisten();
             }, 100);
 
         }
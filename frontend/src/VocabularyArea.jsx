@@ -1,14 +1,14 @@
 import styles from './VocabularyArea.module.css';
-import { createSignal, Show, Switch, Match, For, onMount,createEffect, catchError } from "solid-js";
+import { createSignal, Show, Switch, Match, For, onMount, onCleanup, createEffect, catchError } from "solid-js";
 import { useAppContext } from './AppContext';
-import { tauri_invoke } from './tauri';
+import { tauri_invoke, tauri_listen } from './tauri';
 
 function VocabularyArea() {
   let viewport;
   let dummyContainer;
   let visibleList;
 
-  const { configured, narrowDown, allItems, setAllItems, detail, showConfig,error, loading, usage } = useAppContext();
+  const { configured, narrowDown, refreshItems, allItems, setAllItems, detail, showConfig,error, loading, usage } = useAppContext();
 
   const itemHeight = 32;
   const nodePadding = 10;
@@ -42,18 +42,35 @@ function VocabularyArea() {
   });
 
 
+  let unlisten = null;
+
   onMount(async () => {
     try {
       const words = await tauri_invoke('scan_vocabulary');
       console.log("scan vocabulary", words);
       setAllItems(words);
       usage.refreshUsage();
+
+      // files synced in from other devices while the app is open
+      unlisten = await tauri_listen('vocabulary-changed', async (event) => {
+        console.log("vocabulary changed", event.payload);
+        await refreshItems();
+        if (event.payload.type === 'changed' && detail.detail().query === event.payload.query) {
+          await onLoadWord(event.payload.query);
+        }
+      });
     }
     catch(err) {
       console.log(err);
     }
   });
 
+  onCleanup(() => {
+    if (unlisten) {
+      unlisten();
+    }
+  });
+
   async function onLoadWord(query)  {
     console.log(query);
     try {
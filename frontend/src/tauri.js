@@ -49,4 +49,13 @@ async function tauri_invoke(cmd, payload) {
 }
 
 
-export { tauri_invoke, tauri_dialog };
\ No newline at end of file
+async function tauri_listen(event, handler) {
+    if (window.__TAURI__) {
+        return window.__TAURI__.event.listen(event, handler);
+    }
+    else {
+        return () => {};
+    }
+}
+
+export { tauri_invoke, tauri_dialog, tauri_listen };
\ No newline at end of file
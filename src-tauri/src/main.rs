@@ -6,9 +6,10 @@
 use futures::lock::Mutex;
 use tauri::Manager;
 
-mod entry;
-mod openai;
-mod state;
+use broca_core::{
+    anki, annotations, collection, conflict, export, fulltext, git, queue, review, state, stream,
+    watch,
+};
 mod win_ext;
 use win_ext::WindowExt;
 
@@ -34,10 +35,13 @@ async fn first_time_setup(
     target_lang: &str,
     aws_key: Option<&str>,
     aws_secret: Option<&str>,
+    provider: Option<state::ProviderConfig>,
+    extra_target_langs: Option<Vec<String>>,
 ) -> Result<state::Config, String> {
+    // no tokens or keys in the log
     println!(
-        "{} {} {} {:?} {:?}",
-        workspace_path, openai_token, target_lang, aws_key, aws_secret
+        "Setting up {} for {} {:?}",
+        workspace_path, target_lang, extra_target_langs
     );
     match state.lock().await.first_time_setup(
         workspace_path,
@@ -45,17 +49,123 @@ async fn first_time_setup(
         target_lang,
         aws_key,
         aws_secret,
+        provider,
+        extra_target_langs,
     ) {
         Ok(content) => return Ok(content),
         Err(message) => return Err(message.to_string()),
     }
 }
 
+// The frontend scans once the workspace is known, from then on changes synced into
+// vocabulary/ arrive as "vocabulary-changed" events. Scanning again (after switching
+// workspaces) replaces the watcher.
 #[tauri::command]
 async fn scan_vocabulary(
+    window: tauri::Window,
     state: tauri::State<'_, Mutex<state::State>>,
+    watcher: tauri::State<'_, Mutex<Option<watch::VocabularyWatcher>>>,
 ) -> Result<Vec<String>, String> {
-    match state.lock().await.scan_vocabulary() {
+    let state = state.lock().await.clone();
+    match state.scan_vocabulary() {
+        Ok(content) => {
+            let on_event = move |event: watch::VocabularyEvent| {
+                if let Err(message) = window.emit("vocabulary-changed", event) {
+                    println!("Failed to emit vocabulary change: {}", message);
+                }
+            };
+            match state.watch_vocabulary(on_event) {
+                Ok(new_watcher) => *watcher.lock().await = Some(new_watcher),
+                Err(message) => println!("Not watching the vocabulary folder: {}", message),
+            }
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn list_conflicts(
+    state: tauri::State<'_, Mutex<state::State>>,
+) -> Result<Vec<conflict::Conflict>, String> {
+    match state.lock().await.list_conflicts() {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn resolve_conflict(
+    state: tauri::State<'_, Mutex<state::State>>,
+    query: &str,
+    resolution: conflict::Resolution,
+) -> Result<String, String> {
+    match state.lock().await.resolve_conflict(query, &resolution) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn pull_workspace(
+    state: tauri::State<'_, Mutex<state::State>>,
+) -> Result<git::PullReport, String> {
+    let state = state.lock().await.clone();
+    match state.pull_workspace() {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn push_workspace(state: tauri::State<'_, Mutex<state::State>>) -> Result<(), String> {
+    let state = state.lock().await.clone();
+    match state.push_workspace() {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn word_history(
+    state: tauri::State<'_, Mutex<state::State>>,
+    query: &str,
+) -> Result<Vec<git::Revision>, String> {
+    match state.lock().await.word_history(query) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn revert_word(
+    state: tauri::State<'_, Mutex<state::State>>,
+    query: &str,
+    commit: &str,
+) -> Result<String, String> {
+    match state.lock().await.revert_word(query, commit) {
         Ok(content) => {
             return Ok(content);
         }
@@ -80,12 +190,194 @@ async fn load_word(
     }
 }
 
+#[tauri::command]
+async fn update_annotations(
+    state: tauri::State<'_, Mutex<state::State>>,
+    query: &str,
+    annotations: annotations::Annotations,
+) -> Result<String, String> {
+    match state.lock().await.update_annotations(query, &annotations) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn set_starred(
+    state: tauri::State<'_, Mutex<state::State>>,
+    query: &str,
+    starred: bool,
+) -> Result<String, String> {
+    match state.lock().await.set_starred(query, starred) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn list_tags(
+    state: tauri::State<'_, Mutex<state::State>>,
+) -> Result<Vec<annotations::TagCount>, String> {
+    match state.lock().await.list_tags() {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn words_with_tag(
+    state: tauri::State<'_, Mutex<state::State>>,
+    tag: &str,
+) -> Result<Vec<String>, String> {
+    match state.lock().await.words_with_tag(tag) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn starred_words(state: tauri::State<'_, Mutex<state::State>>) -> Result<Vec<String>, String> {
+    match state.lock().await.starred_words() {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn list_collections(
+    state: tauri::State<'_, Mutex<state::State>>,
+) -> Result<Vec<collection::CollectionSummary>, String> {
+    match state.lock().await.list_collections() {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn load_collection(
+    state: tauri::State<'_, Mutex<state::State>>,
+    name: &str,
+) -> Result<collection::Collection, String> {
+    match state.lock().await.load_collection(name) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn create_collection(
+    state: tauri::State<'_, Mutex<state::State>>,
+    name: &str,
+    description: &str,
+) -> Result<collection::Collection, String> {
+    match state.lock().await.create_collection(name, description) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn delete_collection(
+    state: tauri::State<'_, Mutex<state::State>>,
+    name: &str,
+) -> Result<(), String> {
+    match state.lock().await.delete_collection(name) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn add_to_collection(
+    state: tauri::State<'_, Mutex<state::State>>,
+    name: &str,
+    words: Vec<String>,
+) -> Result<collection::Collection, String> {
+    match state.lock().await.add_to_collection(name, &words) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn remove_from_collection(
+    state: tauri::State<'_, Mutex<state::State>>,
+    name: &str,
+    words: Vec<String>,
+) -> Result<collection::Collection, String> {
+    match state.lock().await.remove_from_collection(name, &words) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
 #[tauri::command]
 async fn query_words(
     state: tauri::State<'_, Mutex<state::State>>,
     query: &str,
+    collection: Option<String>,
 ) -> Result<Vec<String>, String> {
-    match state.lock().await.query_words(query) {
+    match state.lock().await.query_words(query, collection.as_deref()) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn full_text_search(
+    state: tauri::State<'_, Mutex<state::State>>,
+    query: &str,
+    limit: Option<i64>,
+) -> Result<Vec<fulltext::FullTextHit>, String> {
+    match state.lock().await.full_text_search(query, limit) {
         Ok(content) => {
             return Ok(content);
         }
@@ -97,10 +389,174 @@ async fn query_words(
 
 #[tauri::command]
 async fn search(
+    window: tauri::Window,
+    state: tauri::State<'_, Mutex<state::State>>,
+    query: &str,
+    refresh: Option<bool>,
+) -> Result<String, String> {
+    let on_event = move |event: stream::SearchEvent| {
+        if let Err(message) = window.emit("search-progress", event) {
+            println!("Failed to emit search progress: {}", message);
+        }
+    };
+    let state = state.lock().await.clone();
+    match state
+        .search(query, refresh.unwrap_or(false), &on_event)
+        .await
+    {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn add_language(
     state: tauri::State<'_, Mutex<state::State>>,
     query: &str,
+    lang: &str,
 ) -> Result<String, String> {
-    match state.lock().await.search(query).await {
+    let state = state.lock().await.clone();
+    match state.add_language(query, lang).await {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn export_anki(
+    state: tauri::State<'_, Mutex<state::State>>,
+    path: &str,
+    queries: Option<Vec<String>>,
+    options: Option<anki::AnkiOptions>,
+) -> Result<anki::AnkiExportReport, String> {
+    match state
+        .lock()
+        .await
+        .export_anki(path, queries, &options.unwrap_or_default())
+    {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn export_entries(
+    state: tauri::State<'_, Mutex<state::State>>,
+    path: &str,
+    queries: Option<Vec<String>>,
+    format: export::ExportFormat,
+    rows: Option<export::ExportRows>,
+) -> Result<usize, String> {
+    match state
+        .lock()
+        .await
+        .export_entries(path, queries, &format, &rows.unwrap_or_default())
+    {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+// Runs the lookup queue in the background on a copy of the state, so the
+// lookups don't keep other commands waiting.
+fn spawn_queue(window: tauri::Window, state: state::State) {
+    tauri::async_runtime::spawn(async move {
+        let on_event = |event: queue::QueueEvent| {
+            if let Err(message) = window.emit("queue-progress", event) {
+                println!("Failed to emit queue progress: {}", message);
+            }
+        };
+
+        match state.process_queue(&on_event).await {
+            Ok(status) => {
+                if let Err(message) = window.emit("queue-status", status) {
+                    println!("Failed to emit queue status: {}", message);
+                }
+            }
+            Err(message) => {
+                println!("Lookup queue stopped: {}", message);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+async fn import_words(
+    window: tauri::Window,
+    state: tauri::State<'_, Mutex<state::State>>,
+    path: &str,
+) -> Result<export::ImportReport, String> {
+    let state = state.lock().await.clone();
+
+    match state.import_words(path) {
+        Ok(content) => {
+            spawn_queue(window, state);
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn enqueue_words(
+    window: tauri::Window,
+    state: tauri::State<'_, Mutex<state::State>>,
+    queries: Vec<String>,
+) -> Result<queue::QueueStatus, String> {
+    let state = state.lock().await.clone();
+
+    match state.enqueue_words(queries) {
+        Ok(content) => {
+            spawn_queue(window, state);
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn process_queue(
+    window: tauri::Window,
+    state: tauri::State<'_, Mutex<state::State>>,
+) -> Result<queue::QueueStatus, String> {
+    let state = state.lock().await.clone();
+
+    match state.queue_status() {
+        Ok(content) => {
+            spawn_queue(window, state);
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn queue_status(
+    state: tauri::State<'_, Mutex<state::State>>,
+) -> Result<queue::QueueStatus, String> {
+    match state.lock().await.queue_status() {
         Ok(content) => {
             return Ok(content);
         }
@@ -112,7 +568,8 @@ async fn search(
 
 #[tauri::command]
 async fn say(state: tauri::State<'_, Mutex<state::State>>, query: &str) -> Result<String, String> {
-    match state.lock().await.say(query).await {
+    let state = state.lock().await.clone();
+    match state.say(query).await {
         Ok(content) => {
             return Ok(content);
         }
@@ -136,8 +593,9 @@ async fn delete_word(
 #[tauri::command]
 async fn fetch_all_words(
     state: tauri::State<'_, Mutex<state::State>>,
+    collection: Option<String>,
 ) -> Result<Vec<String>, String> {
-    if let Ok(content) = state.lock().await.fetch_all_words() {
+    if let Ok(content) = state.lock().await.fetch_all_words(collection.as_deref()) {
         return Ok(content);
     }
     Err("Can't initialize workspace.".to_string())
@@ -149,9 +607,8 @@ async fn generate_more_examples(
     entry: &str,
     meaning: &str,
 ) -> Result<String, String> {
+    let state = state.lock().await.clone();
     match state
-        .lock()
-        .await
         .search_example_sentences(entry, meaning)
         .await
     {
@@ -172,12 +629,74 @@ async fn load_usage(state: tauri::State<'_, Mutex<state::State>>) -> Result<[i64
     Err("Can't load usage.".to_string())
 }
 
+#[tauri::command]
+async fn fetch_due_words(
+    state: tauri::State<'_, Mutex<state::State>>,
+    limit: Option<i64>,
+    collection: Option<String>,
+) -> Result<Vec<String>, String> {
+    match state.lock().await.fetch_due_words(limit, collection.as_deref()) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn load_review(
+    state: tauri::State<'_, Mutex<state::State>>,
+    query: &str,
+) -> Result<review::ReviewState, String> {
+    match state.lock().await.load_review(query) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+#[tauri::command]
+async fn submit_review(
+    state: tauri::State<'_, Mutex<state::State>>,
+    query: &str,
+    grade: u8,
+) -> Result<review::ReviewState, String> {
+    match state.lock().await.submit_review(query, grade) {
+        Ok(content) => {
+            return Ok(content);
+        }
+        Err(message) => {
+            return Err(message.to_string());
+        }
+    }
+}
+
+// The mutex only guards switching workspaces (load_config, first_time_setup). Commands
+// that wait on the network or the disk work on a clone, which shares the connection pool,
+// so a slow lookup doesn't hold up the rest of the UI.
 fn main() {
     tauri::Builder::default()
         .manage(Mutex::<state::State>::new(state::State::new()))
+        .manage(Mutex::<Option<watch::VocabularyWatcher>>::new(None))
         .setup(|app| {
             let window = app.get_window("main").unwrap();
             // window.open_devtools();
+
+            // Lookups queued (or cut off halfway) when the app last quit carry on in the
+            // background. process_queue puts running jobs back to pending first.
+            let state = app.state::<Mutex<state::State>>();
+            let loaded = tauri::async_runtime::block_on(async {
+                let mut state = state.lock().await;
+                state.load_config().map(|_| state.clone())
+            });
+            if let Ok(state) = loaded {
+                spawn_queue(window.clone(), state);
+            }
             window.set_transparent_titlebar(true);
             //window.maximize().unwrap();
 
@@ -188,13 +707,41 @@ fn main() {
             first_time_setup,
             scan_vocabulary,
             load_word,
+            list_conflicts,
+            resolve_conflict,
+            pull_workspace,
+            push_workspace,
+            word_history,
+            revert_word,
+            update_annotations,
+            set_starred,
+            list_tags,
+            words_with_tag,
+            starred_words,
+            list_collections,
+            load_collection,
+            create_collection,
+            delete_collection,
+            add_to_collection,
+            remove_from_collection,
             query_words,
             search,
             delete_word,
             fetch_all_words,
             say,
             generate_more_examples,
-            load_usage
+            load_usage,
+            fetch_due_words,
+            load_review,
+            submit_review,
+            full_text_search,
+            add_language,
+            export_anki,
+            export_entries,
+            import_words,
+            enqueue_words,
+            process_queue,
+            queue_status
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");